@@ -1,7 +1,8 @@
-use anyhow::{bail, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -11,24 +12,35 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     aim: bool,
+    #[clap(short, long)]
+    trajectory: Option<String>,
+    #[clap(short, long, arg_enum, default_value = "csv")]
+    format: TrajectoryFormat,
+    #[clap(short, long)]
+    compare: bool,
+    #[clap(short, long)]
+    undo: Option<usize>,
+    #[clap(short, long)]
+    save_checkpoint: Option<String>,
+    #[clap(short, long)]
+    resume: Option<String>,
+    #[clap(long, requires = "suffix")]
+    replay_from: Option<usize>,
+    #[clap(long)]
+    suffix: Option<String>,
 }
 
-fn main() -> Result<()> {
-    let opts: Opts = Opts::parse();
-    let mut input = String::new();
-    File::open(opts.input)?.read_to_string(&mut input)?;
-    let lines = input.split('\n').collect::<Vec<&str>>();
-
-    let mut navigation: Box<dyn Navigation> = if opts.aim {
-        Box::new(AimNavigation::default())
-    } else {
-        Box::new(NaiveNavigation::default())
-    };
-
-    navigate(&lines, navigation.as_mut())?;
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum TrajectoryFormat {
+    Csv,
+    Json,
+    Svg,
+}
 
-    let horizontal = navigation.horizontal_position();
-    let vertical = navigation.vertical_position();
+fn main() -> Result<()> {
+    let route = run(Opts::parse())?;
+    let horizontal = route.navigation.horizontal_position();
+    let vertical = route.navigation.vertical_position();
     println!(
         "Resulting position: ({}, {}) (={})",
         horizontal,
@@ -39,6 +51,94 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn run(opts: Opts) -> Result<Route> {
+    if opts.compare && opts.format != TrajectoryFormat::Svg {
+        bail!("Only SVG trajectories can compare both navigations");
+    }
+    let mut input = String::new();
+    File::open(opts.input)?.read_to_string(&mut input)?;
+    let lines = input.split('\n').collect::<Vec<&str>>();
+
+    let instructions = parse_instructions(&lines)?;
+
+    let mut route = match opts.resume {
+        Some(path) => {
+            let mut checkpoint = String::new();
+            File::open(path)?.read_to_string(&mut checkpoint)?;
+            Route::resume(new_navigation(opts.aim), serde_json::from_str(&checkpoint)?)?
+        }
+        None => Route::new(new_navigation(opts.aim)),
+    };
+    // A resumed route has already taken the steps up to its checkpoint
+    if route.step() > instructions.len() {
+        bail!(
+            "Checkpoint at step {} is beyond the {} instructions of the input",
+            route.step(),
+            instructions.len()
+        );
+    }
+    route.execute(&instructions[route.step()..]);
+    if let Some(steps) = opts.undo {
+        route.undo(steps)?;
+    }
+    if let Some(path) = opts.suffix {
+        let mut suffix = String::new();
+        File::open(path)?.read_to_string(&mut suffix)?;
+        let suffix = parse_instructions(&suffix.split('\n').collect::<Vec<&str>>())?;
+        let checkpoint = route.checkpoint_at(opts.replay_from.unwrap_or_else(|| route.step()))?;
+        route.replay(&checkpoint, &suffix)?;
+    }
+
+    if let Some(path) = opts.save_checkpoint {
+        let checkpoint = serde_json::to_string_pretty(&route.checkpoint())?;
+        File::create(path)?.write_all(checkpoint.as_bytes())?;
+    }
+
+    let trajectory = &route.navigation.trajectory;
+    if let Some(path) = opts.trajectory {
+        let output = match opts.format {
+            TrajectoryFormat::Csv => trajectory.to_csv(),
+            TrajectoryFormat::Json => trajectory.to_json()?,
+            TrajectoryFormat::Svg if opts.compare => {
+                // The other navigation follows the route, including undone and replayed steps
+                let mut other = TrajectoryNavigation::new(new_navigation(!opts.aim));
+                navigate(&lines[..route.start.step], &mut other)?;
+                let other_start = other.state();
+                for instruction in &route.instructions {
+                    instruction.apply(&mut other);
+                }
+                let other = Trajectory(other.trajectory.0.split_off(route.start.step));
+                render_depth_profile(&[
+                    (navigation_name(opts.aim), route.start.state, trajectory),
+                    (navigation_name(!opts.aim), other_start, &other),
+                ])
+            }
+            TrajectoryFormat::Svg => {
+                render_depth_profile(&[(navigation_name(opts.aim), route.start.state, trajectory)])
+            }
+        };
+        File::create(path)?.write_all(output.as_bytes())?;
+    }
+
+    Ok(route)
+}
+
+fn new_navigation(aim: bool) -> Box<dyn Navigation> {
+    if aim {
+        Box::new(AimNavigation::default())
+    } else {
+        Box::new(NaiveNavigation::default())
+    }
+}
+
+fn navigation_name(aim: bool) -> &'static str {
+    if aim {
+        "aim"
+    } else {
+        "naive"
+    }
+}
+
 trait Navigation {
     fn handle_forward(&mut self, amount: i64);
     fn handle_down(&mut self, amount: i64);
@@ -46,25 +146,66 @@ trait Navigation {
 
     fn vertical_position(&self) -> i64;
     fn horizontal_position(&self) -> i64;
+    fn aim(&self) -> i64;
+
+    fn restore(&mut self, state: &NavigationState);
+
+    fn kind(&self) -> NavigationKind;
+
+    fn state(&self) -> NavigationState {
+        NavigationState {
+            horizontal: self.horizontal_position(),
+            depth: self.vertical_position(),
+            aim: self.aim(),
+        }
+    }
 }
 
-fn navigate(instructions: &[&str], navigation: &mut dyn Navigation) -> Result<()> {
-    for instruction in instructions {
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Instruction {
+    Forward(i64),
+    Down(i64),
+    Up(i64),
+}
+
+impl Instruction {
+    fn parse(instruction: &str) -> Result<Instruction> {
         let parts = instruction.splitn(2, ' ').collect::<Vec<&str>>();
         let direction = parts
             .get(0)
-            .ok_or_else(|| anyhow::anyhow!("Invalid instruction {}", instruction))?;
+            .ok_or_else(|| anyhow!("Invalid instruction {}", instruction))?;
         let amount = parts
             .get(1)
-            .ok_or_else(|| anyhow::anyhow!("Invalid instruction {}", instruction))?;
+            .ok_or_else(|| anyhow!("Invalid instruction {}", instruction))?;
         let amount = i64::from_str(amount)?;
         match *direction {
-            "forward" => navigation.handle_forward(amount),
-            "up" => navigation.handle_up(amount),
-            "down" => navigation.handle_down(amount),
+            "forward" => Ok(Instruction::Forward(amount)),
+            "up" => Ok(Instruction::Up(amount)),
+            "down" => Ok(Instruction::Down(amount)),
             _ => bail!("Unknown direction {}", direction),
         }
     }
+
+    fn apply(&self, navigation: &mut dyn Navigation) {
+        match *self {
+            Instruction::Forward(amount) => navigation.handle_forward(amount),
+            Instruction::Up(amount) => navigation.handle_up(amount),
+            Instruction::Down(amount) => navigation.handle_down(amount),
+        }
+    }
+}
+
+fn parse_instructions(instructions: &[&str]) -> Result<Vec<Instruction>> {
+    instructions
+        .iter()
+        .map(|instruction| Instruction::parse(instruction))
+        .collect()
+}
+
+fn navigate(instructions: &[&str], navigation: &mut dyn Navigation) -> Result<()> {
+    for instruction in parse_instructions(instructions)? {
+        instruction.apply(navigation);
+    }
     Ok(())
 }
 
@@ -102,6 +243,19 @@ impl Navigation for NaiveNavigation {
     fn horizontal_position(&self) -> i64 {
         self.horizontal
     }
+
+    fn aim(&self) -> i64 {
+        0
+    }
+
+    fn restore(&mut self, state: &NavigationState) {
+        self.horizontal = state.horizontal;
+        self.vertical = state.depth;
+    }
+
+    fn kind(&self) -> NavigationKind {
+        NavigationKind::Naive
+    }
 }
 
 struct AimNavigation {
@@ -139,11 +293,323 @@ impl Navigation for AimNavigation {
     fn horizontal_position(&self) -> i64 {
         self.naive.horizontal_position()
     }
+
+    fn aim(&self) -> i64 {
+        self.aim
+    }
+
+    fn restore(&mut self, state: &NavigationState) {
+        self.naive.restore(state);
+        self.aim = state.aim;
+    }
+
+    fn kind(&self) -> NavigationKind {
+        NavigationKind::Aim
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct NavigationState {
+    horizontal: i64,
+    depth: i64,
+    aim: i64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Trajectory(Vec<NavigationState>);
+
+struct TrajectoryNavigation {
+    inner: Box<dyn Navigation>,
+    trajectory: Trajectory,
+}
+
+impl TrajectoryNavigation {
+    fn new(inner: Box<dyn Navigation>) -> TrajectoryNavigation {
+        TrajectoryNavigation {
+            inner,
+            trajectory: Trajectory::default(),
+        }
+    }
+
+    fn record(&mut self) {
+        self.trajectory.0.push(self.inner.state());
+    }
+}
+
+impl Navigation for TrajectoryNavigation {
+    fn handle_forward(&mut self, amount: i64) {
+        self.inner.handle_forward(amount);
+        self.record();
+    }
+
+    fn handle_down(&mut self, amount: i64) {
+        self.inner.handle_down(amount);
+        self.record();
+    }
+
+    fn handle_up(&mut self, amount: i64) {
+        self.inner.handle_up(amount);
+        self.record();
+    }
+
+    fn vertical_position(&self) -> i64 {
+        self.inner.vertical_position()
+    }
+
+    fn horizontal_position(&self) -> i64 {
+        self.inner.horizontal_position()
+    }
+
+    fn aim(&self) -> i64 {
+        self.inner.aim()
+    }
+
+    fn restore(&mut self, state: &NavigationState) {
+        self.inner.restore(state);
+    }
+
+    fn kind(&self) -> NavigationKind {
+        self.inner.kind()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum NavigationKind {
+    Naive,
+    Aim,
+}
+
+// The navigation is recorded since the state of one doesn't mean the same to the other
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct Checkpoint {
+    navigation: NavigationKind,
+    step: usize,
+    state: NavigationState,
+}
+
+struct Route {
+    navigation: TrajectoryNavigation,
+    start: Checkpoint,
+    instructions: Vec<Instruction>,
+}
+
+impl Route {
+    fn new(navigation: Box<dyn Navigation>) -> Route {
+        let start = Checkpoint {
+            navigation: navigation.kind(),
+            step: 0,
+            state: navigation.state(),
+        };
+        Route {
+            navigation: TrajectoryNavigation::new(navigation),
+            start,
+            instructions: Vec::new(),
+        }
+    }
+
+    fn resume(mut navigation: Box<dyn Navigation>, start: Checkpoint) -> Result<Route> {
+        if navigation.kind() != start.navigation {
+            bail!(
+                "Checkpoint was saved by {:?} navigation, not {:?}",
+                start.navigation,
+                navigation.kind()
+            );
+        }
+        navigation.restore(&start.state);
+        Ok(Route {
+            navigation: TrajectoryNavigation::new(navigation),
+            start,
+            instructions: Vec::new(),
+        })
+    }
+
+    fn step(&self) -> usize {
+        self.start.step + self.instructions.len()
+    }
+
+    fn execute(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            instruction.apply(&mut self.navigation);
+            self.instructions.push(*instruction);
+        }
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            navigation: self.start.navigation,
+            step: self.step(),
+            state: self.navigation.state(),
+        }
+    }
+
+    fn checkpoint_at(&self, step: usize) -> Result<Checkpoint> {
+        let state = match step.checked_sub(self.start.step) {
+            Some(0) => self.start.state,
+            Some(index) if index <= self.instructions.len() => {
+                self.navigation.trajectory.0[index - 1]
+            }
+            _ => bail!(
+                "Step {} is outside of route covering steps {} to {}",
+                step,
+                self.start.step,
+                self.step()
+            ),
+        };
+        Ok(Checkpoint {
+            navigation: self.start.navigation,
+            step,
+            state,
+        })
+    }
+
+    fn undo(&mut self, steps: usize) -> Result<()> {
+        if steps > self.instructions.len() {
+            bail!(
+                "Cannot undo {} steps, only {} taken since step {}",
+                steps,
+                self.instructions.len(),
+                self.start.step
+            );
+        }
+        let remaining = self.instructions.len() - steps;
+        self.instructions.truncate(remaining);
+        self.navigation.trajectory.0.truncate(remaining);
+        let state = *self
+            .navigation
+            .trajectory
+            .0
+            .last()
+            .unwrap_or(&self.start.state);
+        self.navigation.restore(&state);
+        Ok(())
+    }
+
+    fn replay(&mut self, checkpoint: &Checkpoint, suffix: &[Instruction]) -> Result<()> {
+        if checkpoint.step < self.start.step || checkpoint.step > self.step() {
+            bail!(
+                "Checkpoint at step {} is outside of route covering steps {} to {}",
+                checkpoint.step,
+                self.start.step,
+                self.step()
+            );
+        }
+        self.undo(self.step() - checkpoint.step)?;
+        if checkpoint.navigation != self.start.navigation
+            || self.navigation.state() != checkpoint.state
+        {
+            bail!(
+                "Checkpoint at step {} is not part of this route",
+                checkpoint.step
+            );
+        }
+        self.execute(suffix);
+        Ok(())
+    }
+}
+
+impl Trajectory {
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("step,horizontal,depth,aim\n");
+        for (step, point) in self.0.iter().enumerate() {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                step + 1,
+                point.horizontal,
+                point.depth,
+                point.aim
+            ));
+        }
+        csv
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.0)?)
+    }
+
+    // The trajectory only records the states after each instruction, so the state it started
+    // from comes first
+    fn points_from(&self, start: NavigationState) -> impl Iterator<Item = (i64, i64)> + '_ {
+        std::iter::once(start)
+            .chain(self.0.iter().copied())
+            .map(|p| (p.horizontal, p.depth))
+    }
+}
+
+const SVG_WIDTH: f64 = 800.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 40.0;
+const SVG_COLORS: [&str; 4] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd"];
+
+fn render_depth_profile(series: &[(&str, NavigationState, &Trajectory)]) -> String {
+    let points = series
+        .iter()
+        .flat_map(|(_, start, trajectory)| trajectory.points_from(*start))
+        .collect::<Vec<_>>();
+    let min_x = points.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let max_x = points.iter().map(|(x, _)| *x).max().unwrap_or(0);
+    // The surface is always in view, even for routes resumed below it
+    let min_y = points.iter().map(|(_, y)| *y).min().unwrap_or(0).min(0);
+    let max_y = points.iter().map(|(_, y)| *y).max().unwrap_or(0).max(0);
+    let scale_x = (SVG_WIDTH - 2.0 * SVG_MARGIN) / ((max_x - min_x).max(1) as f64);
+    let scale_y = (SVG_HEIGHT - 2.0 * SVG_MARGIN) / ((max_y - min_y).max(1) as f64);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        SVG_WIDTH, SVG_HEIGHT, SVG_WIDTH, SVG_HEIGHT
+    );
+    svg.push_str(&format!(
+        "  <rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n",
+        SVG_WIDTH, SVG_HEIGHT
+    ));
+    // Depth grows downwards, just like the y axis of an SVG
+    let surface = SVG_MARGIN + (0 - min_y) as f64 * scale_y;
+    svg.push_str(&format!(
+        "  <line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"#999\" stroke-dasharray=\"4\"/>\n",
+        SVG_MARGIN,
+        surface,
+        SVG_WIDTH - SVG_MARGIN,
+        surface
+    ));
+    for (index, (name, start, trajectory)) in series.iter().enumerate() {
+        let color = SVG_COLORS[index % SVG_COLORS.len()];
+        let polyline = trajectory
+            .points_from(*start)
+            .map(|(x, y)| {
+                format!(
+                    "{:.1},{:.1}",
+                    SVG_MARGIN + (x - min_x) as f64 * scale_x,
+                    SVG_MARGIN + (y - min_y) as f64 * scale_y
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        svg.push_str(&format!(
+            "  <polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+            color, polyline
+        ));
+        svg.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" fill=\"{}\" font-family=\"sans-serif\" font-size=\"14\">{}</text>\n",
+            SVG_MARGIN,
+            SVG_MARGIN / 2.0 + index as f64 * 16.0,
+            color,
+            name
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{navigate, AimNavigation, NaiveNavigation, Navigation};
+    use crate::{
+        navigate, parse_instructions, render_depth_profile, AimNavigation, Checkpoint, Instruction,
+        NaiveNavigation, Navigation, NavigationKind, NavigationState, Route, TrajectoryNavigation,
+    };
+    use crate::{run, Opts};
+    use clap::Parser;
+    use std::fs::File;
+    use std::io::Write;
 
     struct RecordingNavigation {
         pub instructions: Vec<(String, i64)>,
@@ -169,6 +635,16 @@ mod test {
         fn horizontal_position(&self) -> i64 {
             0
         }
+
+        fn aim(&self) -> i64 {
+            0
+        }
+
+        fn restore(&mut self, _state: &NavigationState) {}
+
+        fn kind(&self) -> NavigationKind {
+            NavigationKind::Naive
+        }
     }
 
     impl Default for RecordingNavigation {
@@ -212,7 +688,7 @@ mod test {
 
         navigation.handle_forward(5);
         navigation.handle_down(5);
-        navigation.handle_forward(0);
+        navigation.handle_forward(8);
         navigation.handle_up(3);
         navigation.handle_down(8);
         navigation.handle_forward(2);
@@ -227,7 +703,7 @@ mod test {
 
         navigation.handle_forward(5);
         navigation.handle_down(5);
-        navigation.handle_forward(0);
+        navigation.handle_forward(8);
         navigation.handle_up(3);
         navigation.handle_down(8);
         navigation.handle_forward(2);
@@ -235,4 +711,269 @@ mod test {
         assert_eq!(15, navigation.horizontal_position());
         assert_eq!(60, navigation.vertical_position());
     }
+
+    #[test]
+    fn test_trajectory_aim() {
+        let instructions = ["forward 5", "down 5", "forward 8", "up 3"];
+        let mut navigation = TrajectoryNavigation::new(Box::new(AimNavigation::default()));
+
+        let result = navigate(&instructions, &mut navigation);
+        assert!(result.is_ok());
+
+        let expected = vec![
+            NavigationState {
+                horizontal: 5,
+                depth: 0,
+                aim: 0,
+            },
+            NavigationState {
+                horizontal: 5,
+                depth: 0,
+                aim: 5,
+            },
+            NavigationState {
+                horizontal: 13,
+                depth: 40,
+                aim: 5,
+            },
+            NavigationState {
+                horizontal: 13,
+                depth: 40,
+                aim: 2,
+            },
+        ];
+        assert_eq!(expected, navigation.trajectory.0);
+        assert_eq!(13, navigation.horizontal_position());
+        assert_eq!(40, navigation.vertical_position());
+    }
+
+    #[test]
+    fn test_trajectory_export() {
+        let instructions = ["forward 5", "down 5"];
+        let mut navigation = TrajectoryNavigation::new(Box::new(NaiveNavigation::default()));
+        navigate(&instructions, &mut navigation).unwrap();
+
+        assert_eq!(
+            "step,horizontal,depth,aim\n1,5,0,0\n2,5,5,0\n",
+            navigation.trajectory.to_csv()
+        );
+
+        let json = navigation.trajectory.to_json();
+        assert!(json.is_ok());
+        let json: serde_json::Value = serde_json::from_str(&json.unwrap()).unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {"horizontal": 5, "depth": 0, "aim": 0},
+                {"horizontal": 5, "depth": 5, "aim": 0},
+            ]),
+            json
+        );
+    }
+
+    #[test]
+    fn test_render_depth_profile() {
+        let instructions = ["forward 5", "down 5", "forward 8"];
+        let mut naive = TrajectoryNavigation::new(Box::new(NaiveNavigation::default()));
+        let mut aim = TrajectoryNavigation::new(Box::new(AimNavigation::default()));
+        navigate(&instructions, &mut naive).unwrap();
+        navigate(&instructions, &mut aim).unwrap();
+
+        let start = NavigationState::default();
+        let svg = render_depth_profile(&[
+            ("naive", start, &naive.trajectory),
+            ("aim", start, &aim.trajectory),
+        ]);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(2, svg.matches("<polyline").count());
+        assert!(svg.contains(">naive</text>"));
+        assert!(svg.contains(">aim</text>"));
+
+        // A resumed route starts where its checkpoint left off rather than at the surface
+        let mut route = Route::resume(
+            Box::new(AimNavigation::default()),
+            Checkpoint {
+                navigation: NavigationKind::Aim,
+                step: 2,
+                state: NavigationState {
+                    horizontal: 5,
+                    depth: 0,
+                    aim: 5,
+                },
+            },
+        )
+        .unwrap();
+        route.execute(&[Instruction::Forward(8)]);
+        let svg = render_depth_profile(&[("aim", route.start.state, &route.navigation.trajectory)]);
+        assert!(svg.contains("points=\"40.0,40.0 760.0,360.0\""));
+    }
+
+    #[test]
+    fn test_parse_instructions() {
+        let result = parse_instructions(&["forward 5", "down 5", "up 3"]);
+        assert!(result.is_ok());
+        assert_eq!(
+            vec![
+                Instruction::Forward(5),
+                Instruction::Down(5),
+                Instruction::Up(3)
+            ],
+            result.unwrap()
+        );
+
+        assert!(parse_instructions(&["sideways 5"]).is_err());
+        assert!(parse_instructions(&["forward"]).is_err());
+    }
+
+    #[test]
+    fn test_route_undo() {
+        let instructions = parse_instructions(&[
+            "forward 5",
+            "down 5",
+            "forward 8",
+            "up 3",
+            "down 8",
+            "forward 2",
+        ])
+        .unwrap();
+        let mut route = Route::new(Box::new(AimNavigation::default()));
+        route.execute(&instructions);
+        assert_eq!(60, route.navigation.vertical_position());
+
+        assert!(route.undo(3).is_ok());
+        assert_eq!(3, route.step());
+        assert_eq!(3, route.navigation.trajectory.0.len());
+        assert_eq!(
+            NavigationState {
+                horizontal: 13,
+                depth: 40,
+                aim: 5,
+            },
+            route.navigation.state()
+        );
+
+        assert!(route.undo(4).is_err());
+        assert!(route.undo(3).is_ok());
+        assert_eq!(NavigationState::default(), route.navigation.state());
+    }
+
+    #[test]
+    fn test_route_replay() {
+        let instructions = parse_instructions(&["forward 5", "down 5", "forward 8"]).unwrap();
+        let mut route = Route::new(Box::new(AimNavigation::default()));
+        route.execute(&instructions[..2]);
+        let checkpoint = route.checkpoint();
+        route.execute(&instructions[2..]);
+        assert_eq!(40, route.navigation.vertical_position());
+        assert_eq!(checkpoint, route.checkpoint_at(2).unwrap());
+        assert!(route.checkpoint_at(4).is_err());
+
+        // Skip the dive and go straight forward instead
+        let result = route.replay(&checkpoint, &[Instruction::Up(5), Instruction::Forward(8)]);
+        assert!(result.is_ok());
+        assert_eq!(4, route.step());
+        assert_eq!(13, route.navigation.horizontal_position());
+        assert_eq!(0, route.navigation.vertical_position());
+
+        let foreign = Checkpoint {
+            navigation: NavigationKind::Aim,
+            step: 1,
+            state: NavigationState {
+                horizontal: 1,
+                depth: 1,
+                aim: 1,
+            },
+        };
+        assert!(route.replay(&foreign, &[]).is_err());
+    }
+
+    #[test]
+    fn test_route_resume() {
+        let instructions = parse_instructions(&["forward 5", "down 5", "forward 8"]).unwrap();
+        let mut route = Route::new(Box::new(AimNavigation::default()));
+        route.execute(&instructions[..2]);
+        let checkpoint = serde_json::to_string(&route.checkpoint()).unwrap();
+
+        let checkpoint: Checkpoint = serde_json::from_str(&checkpoint).unwrap();
+        assert!(Route::resume(Box::new(NaiveNavigation::default()), checkpoint).is_err());
+        let mut resumed = Route::resume(Box::new(AimNavigation::default()), checkpoint).unwrap();
+        resumed.execute(&instructions[2..]);
+        assert_eq!(3, resumed.step());
+        assert_eq!(13, resumed.navigation.horizontal_position());
+        assert_eq!(40, resumed.navigation.vertical_position());
+        assert!(resumed.undo(2).is_err());
+    }
+
+    #[test]
+    fn test_resume_from_cli() {
+        let dir = std::env::temp_dir().join(format!("day02-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input");
+        let checkpoint = dir.join("checkpoint.json");
+        let svg = dir.join("route.svg");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"forward 5\ndown 5\nforward 8")
+            .unwrap();
+        let input = input.to_str().unwrap();
+        let checkpoint = checkpoint.to_str().unwrap();
+
+        let route = run(Opts::parse_from([
+            "day02", "-i", input, "-a", "--undo", "1", "-s", checkpoint,
+        ]))
+        .unwrap();
+        assert_eq!(2, route.step());
+
+        let route = run(Opts::parse_from([
+            "day02",
+            "-i",
+            input,
+            "-a",
+            "--resume",
+            checkpoint,
+            "-t",
+            svg.to_str().unwrap(),
+            "-f",
+            "svg",
+            "-c",
+        ]))
+        .unwrap();
+        assert_eq!(3, route.step());
+        assert_eq!(13, route.navigation.horizontal_position());
+        assert_eq!(40, route.navigation.vertical_position());
+        assert_eq!(1, route.navigation.trajectory.0.len());
+
+        // The checkpoint was saved with aim, so it can't be resumed without it
+        let result = run(Opts::parse_from([
+            "day02", "-i", input, "--resume", checkpoint,
+        ]));
+        assert!(result.is_err());
+
+        let short = dir.join("short");
+        File::create(&short)
+            .unwrap()
+            .write_all(b"forward 5")
+            .unwrap();
+        let result = run(Opts::parse_from([
+            "day02",
+            "-i",
+            short.to_str().unwrap(),
+            "-a",
+            "--resume",
+            checkpoint,
+        ]));
+        assert!(result.is_err());
+
+        let result = run(Opts::parse_from([
+            "day02",
+            "-i",
+            input,
+            "-t",
+            svg.to_str().unwrap(),
+            "-f",
+            "csv",
+            "-c",
+        ]));
+        assert!(result.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
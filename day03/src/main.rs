@@ -1,8 +1,8 @@
 use anyhow::{anyhow, bail, Result};
-use clap::Parser;
+use clap::{ArgEnum, Parser};
+use num_bigint::BigUint;
 use std::fs::File;
 use std::io::Read;
-use std::ops::Shr;
 
 #[derive(Parser)]
 #[clap(version = "1.0", author = "Raniz")]
@@ -11,136 +11,701 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     sieve: bool,
+    #[clap(long, arg_enum, default_value = "most-common")]
+    oxygen_selection: Selection,
+    #[clap(long, arg_enum, default_value = "one")]
+    oxygen_tie: TieBreak,
+    #[clap(long, arg_enum, default_value = "least-common")]
+    co2_selection: Selection,
+    #[clap(long, arg_enum, default_value = "zero")]
+    co2_tie: TieBreak,
+    #[clap(short, long)]
+    trace: bool,
+    #[clap(short, long, default_value = "2")]
+    base: u32,
+    #[clap(short, long)]
+    alphabet: Option<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum Selection {
+    MostCommon,
+    LeastCommon,
+}
+
+// Zero and one are the lowest and highest symbol of the alphabet
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum TieBreak {
+    #[clap(alias = "lowest")]
+    Zero,
+    #[clap(alias = "highest")]
+    One,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct SievePolicy {
+    selection: Selection,
+    tie_break: TieBreak,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct SieveStep {
+    index: usize,
+    counts: Vec<usize>,
+    kept: char,
+    remaining: usize,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Alphabet(Vec<char>);
+
+const DIGITS: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
     File::open(opts.input)?.read_to_string(&mut input)?;
     let lines = input.split('\n').collect::<Vec<&str>>();
+    let alphabet = match opts.alphabet {
+        Some(symbols) => Alphabet::new(&symbols)?,
+        None => Alphabet::base(opts.base)?,
+    };
+    let report = Report::parse(&lines, &alphabet)?;
 
     if opts.sieve {
-        let oxygen = sieve(&lines, 0, false)?;
-        dbg!(oxygen);
-        let co2 = sieve(&lines, 0, true)?;
-        dbg!(co2);
+        let oxygen_policy = SievePolicy::new(opts.oxygen_selection, opts.oxygen_tie);
+        let co2_policy = SievePolicy::new(opts.co2_selection, opts.co2_tie);
+        let mut oxygen_steps = Vec::new();
+        let oxygen = sieve(&report, oxygen_policy, &mut oxygen_steps);
+        let mut co2_steps = Vec::new();
+        let co2 = sieve(&report, co2_policy, &mut co2_steps);
+
+        // The steps up to a failed sieve are printed too since they show where it went wrong
+        if opts.trace {
+            print_trace("oxygen", &alphabet, &oxygen_steps);
+            print_trace("co2", &alphabet, &co2_steps);
+        }
+        let (oxygen, co2) = (oxygen?, co2?);
 
         println!(
             "oxygen = {}, co2 = {}, oxygen * co2 = {}",
             oxygen,
             co2,
-            oxygen * co2
+            &oxygen * &co2
         );
     } else {
-        let (gamma, num_bits) = calc_gamma(&lines)?;
-        let epsilon = calc_epsilon(gamma, num_bits);
+        let (gamma, _) = calc_gamma(&report)?;
+        let epsilon = calc_epsilon(&report)?;
 
         println!(
             "gamma = {}, epsilon = {}, epsilon * gamma = {}",
             gamma,
             epsilon,
-            epsilon * gamma
+            &epsilon * &gamma
         );
     }
 
     Ok(())
 }
 
-fn calc_epsilon(gamma: u32, num_bits: u8) -> u32 {
-    !gamma & (u32::MAX.shr(32 - num_bits))
+fn print_trace(rating: &str, alphabet: &Alphabet, steps: &[SieveStep]) {
+    for step in steps {
+        let counts = alphabet
+            .0
+            .iter()
+            .zip(step.counts.iter())
+            .map(|(symbol, count)| format!("{} = {}", symbol, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{} column {}: {}, keeping {}, {} candidates left",
+            rating, step.index, counts, step.kept, step.remaining
+        );
+    }
+}
+
+impl Alphabet {
+    fn new(symbols: &str) -> Result<Alphabet> {
+        let symbols = symbols.chars().collect::<Vec<_>>();
+        if symbols.len() < 2 || symbols.len() > 256 {
+            bail!("Alphabet must have between 2 and 256 symbols");
+        }
+        if let Some((index, symbol)) = symbols
+            .iter()
+            .enumerate()
+            .find(|(index, symbol)| symbols[..*index].contains(symbol))
+        {
+            bail!(
+                "Duplicate symbol {} at position {} in alphabet",
+                symbol,
+                index + 1
+            );
+        }
+        Ok(Alphabet(symbols))
+    }
+
+    fn base(base: u32) -> Result<Alphabet> {
+        if base as usize > DIGITS.len() {
+            bail!("Base must be at most {}", DIGITS.len());
+        }
+        Alphabet::new(&DIGITS[..base as usize])
+    }
+
+    fn radix(&self) -> u32 {
+        self.0.len() as u32
+    }
+
+    fn index_of(&self, symbol: char) -> Option<usize> {
+        self.0.iter().position(|s| *s == symbol)
+    }
+
+    fn value(&self, digits: &[u8]) -> Result<BigUint> {
+        BigUint::from_radix_be(digits, self.radix())
+            .ok_or_else(|| anyhow!("Invalid digits {:?}", digits))
+    }
 }
 
-fn calc_gamma(lines: &[&str]) -> Result<(u32, u8)> {
-    let bit_counts = lines
+fn check_width(lines: &[&str]) -> Result<usize> {
+    let width = lines
+        .first()
+        .map(|line| line.chars().count())
+        .ok_or_else(|| anyhow!("No input"))?;
+    match lines
         .iter()
-        .map(|line| {
-            line.chars()
-                .map(|b| match b {
-                    '0' => Ok(0),
-                    '1' => Ok(1),
-                    _ => bail!("Invalid bit {}", b),
-                })
-                .collect::<Result<Vec<u32>>>()
-        })
-        .reduce(|a, b| match (a, b) {
-            (Ok(a), Ok(b)) => Ok(a
-                .iter()
-                .zip(b.iter())
-                .map(|(av, bv)| av + bv)
-                .collect::<Vec<u32>>()),
-            (_, Err(e)) => Err(e),
-            (Err(e), _) => Err(e),
+        .enumerate()
+        .find(|(_, line)| line.chars().count() != width)
+    {
+        Some((index, line)) => bail!(
+            "Line {} ({}) is {} symbols wide but line 1 is {} symbols wide",
+            index + 1,
+            line,
+            line.chars().count(),
+            width
+        ),
+        None => Ok(width),
+    }
+}
+
+struct Report<'a> {
+    alphabet: &'a Alphabet,
+    lines: Vec<&'a str>,
+    // One bitset per column and symbol where bit n is set if line n has that symbol in the column
+    columns: Vec<Vec<Vec<u64>>>,
+}
+
+impl<'a> Report<'a> {
+    fn parse(lines: &[&'a str], alphabet: &'a Alphabet) -> Result<Report<'a>> {
+        let width = check_width(lines)?;
+        let words = lines.len().div_ceil(64);
+        let mut columns = vec![vec![vec![0u64; words]; alphabet.0.len()]; width];
+        for (index, line) in lines.iter().enumerate() {
+            for (column, symbol) in columns.iter_mut().zip(line.chars()) {
+                let symbol_index = alphabet
+                    .index_of(symbol)
+                    .ok_or_else(|| anyhow!("Invalid symbol {} on line {}", symbol, index + 1))?;
+                column[symbol_index][index / 64] |= 1 << (index % 64);
+            }
+        }
+        Ok(Report {
+            alphabet,
+            lines: lines.to_vec(),
+            columns,
         })
-        .unwrap_or_else(|| Err(anyhow!("No input")))?;
-    let num_bits = bit_counts.len() as u8;
-    let gamma = bit_counts
+    }
+
+    fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn all_lines(&self) -> Vec<u64> {
+        let mut candidates = vec![u64::MAX; self.lines.len().div_ceil(64)];
+        if let Some(last) = candidates.last_mut() {
+            if !self.lines.len().is_multiple_of(64) {
+                *last = (1 << (self.lines.len() % 64)) - 1;
+            }
+        }
+        candidates
+    }
+
+    fn parse_value(&self, line: &str) -> Result<BigUint> {
+        let digits = line
+            .chars()
+            .map(|symbol| {
+                self.alphabet
+                    .index_of(symbol)
+                    .map(|index| index as u8)
+                    .ok_or_else(|| anyhow!("Invalid symbol {} in {}", symbol, line))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.alphabet.value(&digits)
+    }
+}
+
+fn count_ones(column: &[u64], candidates: &[u64]) -> usize {
+    column
         .iter()
-        .rev()
-        .enumerate()
-        .map(|(i, v)| {
-            let bit: u32 = if *v >= (lines.len() / 2) as u32 { 1 } else { 0 };
-            bit << i
+        .zip(candidates.iter())
+        .map(|(bits, candidates)| (bits & candidates).count_ones() as usize)
+        .sum()
+}
+
+fn count_symbols(column: &[Vec<u64>], candidates: &[u64]) -> Vec<usize> {
+    column
+        .iter()
+        .map(|symbol| count_ones(symbol, candidates))
+        .collect()
+}
+
+// Ties go to the highest symbol, which for binary reports means that ties go to 1
+fn calc_gamma(report: &Report) -> Result<(BigUint, usize)> {
+    let candidates = report.all_lines();
+    let digits = report
+        .columns
+        .iter()
+        .map(|column| {
+            count_symbols(column, &candidates)
+                .into_iter()
+                .enumerate()
+                .max_by_key(|(_, count)| *count)
+                .map(|(symbol, _)| symbol as u8)
+                .unwrap_or(0)
         })
-        .sum();
-    Ok((gamma, num_bits))
+        .collect::<Vec<u8>>();
+    Ok((report.alphabet.value(&digits)?, report.width()))
 }
 
-fn sieve(lines: &[&str], index: usize, inverse: bool) -> Result<u32> {
-    let ones = lines
+// Symbols that never occur count as least frequent, so that a binary epsilon is always the
+// complement of gamma. Ties go to the lowest symbol.
+fn calc_epsilon(report: &Report) -> Result<BigUint> {
+    let candidates = report.all_lines();
+    let digits = report
+        .columns
         .iter()
-        .filter(|line| &line[index..index + 1] == "1")
-        .count();
-    let zeroes = lines.len() - ones;
-    let filter = if (inverse && ones < zeroes) || (!inverse && ones >= zeroes) {
-        "1"
-    } else {
-        "0"
-    };
-    let mut candidates = lines.to_vec();
-    candidates.retain(|line| &line[index..index + 1] == filter);
-    match candidates.as_slice() {
-        [hit] => Ok(u32::from_str_radix(hit, 2)?),
-        hits => sieve(hits, index + 1, inverse),
+        .map(|column| {
+            count_symbols(column, &candidates)
+                .into_iter()
+                .enumerate()
+                .min_by_key(|(_, count)| *count)
+                .map(|(symbol, _)| symbol as u8)
+                .unwrap_or(0)
+        })
+        .collect::<Vec<u8>>();
+    report.alphabet.value(&digits)
+}
+
+impl SievePolicy {
+    fn new(selection: Selection, tie_break: TieBreak) -> SievePolicy {
+        SievePolicy {
+            selection,
+            tie_break,
+        }
+    }
+
+    // Returns the symbols that should be kept given the count for each symbol. Symbols that don't
+    // occur among the candidates are never selected.
+    fn select(&self, counts: &[usize]) -> Vec<usize> {
+        let present = counts.iter().filter(|count| **count > 0);
+        let best = match self.selection {
+            Selection::MostCommon => present.max(),
+            Selection::LeastCommon => present.min(),
+        };
+        match best {
+            Some(best) => (0..counts.len())
+                .filter(|symbol| counts[*symbol] == *best)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn break_tie(&self, symbols: &[usize]) -> Option<usize> {
+        match (symbols, self.tie_break) {
+            ([symbol], _) => Some(*symbol),
+            (_, TieBreak::Zero) => symbols.first().cloned(),
+            (_, TieBreak::One) => symbols.last().cloned(),
+            (_, TieBreak::Error) => None,
+        }
+    }
+}
+
+fn sieve(report: &Report, policy: SievePolicy, steps: &mut Vec<SieveStep>) -> Result<BigUint> {
+    let mut candidates = report.all_lines();
+    for (index, column) in report.columns.iter().enumerate() {
+        let counts = count_symbols(column, &candidates);
+        let selected = policy.select(&counts);
+        let keep = policy.break_tie(&selected).ok_or_else(|| {
+            let symbols = selected
+                .iter()
+                .map(|symbol| report.alphabet.0[*symbol].to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow!(
+                "Tie between {} with {} lines each at column {}",
+                symbols,
+                selected.first().map(|symbol| counts[*symbol]).unwrap_or(0),
+                index
+            )
+        })?;
+        for (candidates, bits) in candidates.iter_mut().zip(column[keep].iter()) {
+            *candidates &= *bits;
+        }
+        let remaining = counts[keep];
+        steps.push(SieveStep {
+            index,
+            counts,
+            kept: report.alphabet.0[keep],
+            remaining,
+        });
+        if remaining == 1 {
+            break;
+        }
     }
+    // Lines that survive every column are identical, so the first one will do
+    let (word, bits) = candidates
+        .iter()
+        .enumerate()
+        .find(|(_, bits)| **bits != 0)
+        .ok_or_else(|| anyhow!("No lines left after sieving"))?;
+    report.parse_value(report.lines[word * 64 + bits.trailing_zeros() as usize])
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{calc_epsilon, calc_gamma, sieve};
+    use crate::{
+        calc_epsilon, calc_gamma, check_width, sieve, Alphabet, Report, Selection, SievePolicy,
+        SieveStep, TieBreak,
+    };
+    use anyhow::Result;
+    use num_bigint::BigUint;
+
+    // String based implementations, kept as a reference for the bit-parallel ones
+    fn reference_gamma(lines: &[&str]) -> BigUint {
+        let bits = (0..lines[0].len())
+            .map(|index| {
+                let ones = lines
+                    .iter()
+                    .filter(|line| &line[index..index + 1] == "1")
+                    .count();
+                if ones >= lines.len() - ones {
+                    1
+                } else {
+                    0
+                }
+            })
+            .collect::<Vec<u8>>();
+        BigUint::from_radix_be(&bits, 2).unwrap()
+    }
+
+    fn run_sieve(report: &Report, policy: SievePolicy) -> Result<(BigUint, Vec<SieveStep>)> {
+        let mut steps = Vec::new();
+        let value = sieve(report, policy, &mut steps)?;
+        Ok((value, steps))
+    }
+
+    fn reference_sieve(lines: &[&str], index: usize, inverse: bool) -> Result<BigUint> {
+        let ones = lines
+            .iter()
+            .filter(|line| &line[index..index + 1] == "1")
+            .count();
+        let zeroes = lines.len() - ones;
+        let filter = if (inverse && ones < zeroes) || (!inverse && ones >= zeroes) {
+            "1"
+        } else {
+            "0"
+        };
+        let mut candidates = lines.to_vec();
+        candidates.retain(|line| &line[index..index + 1] == filter);
+        match candidates.as_slice() {
+            [hit] => Ok(BigUint::parse_bytes(hit.as_bytes(), 2).unwrap()),
+            hits => reference_sieve(hits, index + 1, inverse),
+        }
+    }
+
+    fn oxygen() -> SievePolicy {
+        SievePolicy::new(Selection::MostCommon, TieBreak::One)
+    }
+
+    fn co2() -> SievePolicy {
+        SievePolicy::new(Selection::LeastCommon, TieBreak::Zero)
+    }
+
+    fn generate_report(lines: usize, width: usize, seed: u64) -> String {
+        let mut state = seed;
+        let mut report = String::with_capacity(lines * (width + 1));
+        for _ in 0..lines {
+            for _ in 0..width {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                report.push(if state >> 63 == 1 { '1' } else { '0' });
+            }
+            report.push('\n');
+        }
+        report.pop();
+        report
+    }
 
     #[test]
     fn test_sieve() {
+        let binary = Alphabet::base(2).unwrap();
         let input = [
             "00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000",
             "11001", "00010", "01010",
         ];
 
-        let result = sieve(&input, 0, false);
+        let report = Report::parse(&input, &binary).unwrap();
+
+        let result = run_sieve(&report, oxygen());
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(23u32), result.unwrap().0);
+
+        let result = run_sieve(&report, co2());
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(10u32), result.unwrap().0);
+    }
+
+    #[test]
+    fn test_sieve_duplicate_lines() {
+        let binary = Alphabet::base(2).unwrap();
+        let input = ["101", "101", "000"];
+        let report = Report::parse(&input, &binary).unwrap();
+
+        let result = run_sieve(&report, oxygen());
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(5u32), result.unwrap().0);
+
+        let result = run_sieve(&report, co2());
         assert!(result.is_ok());
-        assert_eq!(23, result.unwrap());
+        assert_eq!(BigUint::from(0u32), result.unwrap().0);
+    }
+
+    #[test]
+    fn test_sieve_policies() {
+        let binary = Alphabet::base(2).unwrap();
+        let input = ["00", "01", "10", "11"];
+        let report = Report::parse(&input, &binary).unwrap();
+
+        let result = run_sieve(
+            &report,
+            SievePolicy::new(Selection::MostCommon, TieBreak::Zero),
+        );
+        assert_eq!(BigUint::from(0u32), result.unwrap().0);
+        let result = run_sieve(
+            &report,
+            SievePolicy::new(Selection::LeastCommon, TieBreak::One),
+        );
+        assert_eq!(BigUint::from(3u32), result.unwrap().0);
+
+        let result = run_sieve(
+            &report,
+            SievePolicy::new(Selection::MostCommon, TieBreak::Error),
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            "Tie between 0, 1 with 2 lines each at column 0",
+            result.unwrap_err().to_string()
+        );
+
+        let input = ["000", "001", "010"];
+        let report = Report::parse(&input, &binary).unwrap();
+        let mut steps = Vec::new();
+        let result = sieve(
+            &report,
+            SievePolicy::new(Selection::MostCommon, TieBreak::Error),
+            &mut steps,
+        );
+        assert_eq!(
+            "Tie between 0, 1 with 1 lines each at column 2",
+            result.unwrap_err().to_string()
+        );
+        assert_eq!(
+            vec![0, 1],
+            steps.iter().map(|s| s.index).collect::<Vec<_>>()
+        );
 
-        let result = sieve(&input, 0, true);
+        let input = ["110", "101", "011"];
+        let report = Report::parse(&input, &binary).unwrap();
+        let result = run_sieve(
+            &report,
+            SievePolicy::new(Selection::LeastCommon, TieBreak::Error),
+        );
         assert!(result.is_ok());
-        assert_eq!(10, result.unwrap());
+        let (value, steps) = result.unwrap();
+        assert_eq!(BigUint::from(3u32), value);
+        assert_eq!(
+            vec![SieveStep {
+                index: 0,
+                counts: vec![1, 2],
+                kept: '0',
+                remaining: 1,
+            }],
+            steps
+        );
     }
 
     #[test]
     fn test_calc_gamma() {
+        let binary = Alphabet::base(2).unwrap();
         let input = [
             "00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000",
             "11001", "00010", "01010",
         ];
-        let result = calc_gamma(&input);
+        let report = Report::parse(&input, &binary).unwrap();
+        let result = calc_gamma(&report);
+        assert!(result.is_ok());
+        assert_eq!((BigUint::from(22u32), 5), result.unwrap());
+    }
+
+    #[test]
+    fn test_calc_gamma_odd_count() {
+        let binary = Alphabet::base(2).unwrap();
+        let input = ["110", "101", "011"];
+        let report = Report::parse(&input, &binary).unwrap();
+        let result = calc_gamma(&report);
+        assert!(result.is_ok());
+        assert_eq!((BigUint::from(7u32), 3), result.unwrap());
+
+        let input = ["100", "010", "001"];
+        let report = Report::parse(&input, &binary).unwrap();
+        let result = calc_gamma(&report);
         assert!(result.is_ok());
-        assert_eq!((22, 5), result.unwrap());
+        assert_eq!((BigUint::from(0u32), 3), result.unwrap());
     }
 
     #[test]
     fn test_calc_epsilon() {
-        let gamma = 22;
-        let num_bits = 5;
+        let binary = Alphabet::base(2).unwrap();
+        let input = [
+            "00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000",
+            "11001", "00010", "01010",
+        ];
+        let report = Report::parse(&input, &binary).unwrap();
+
+        let epsilon = calc_epsilon(&report);
+        assert!(epsilon.is_ok());
+        assert_eq!(BigUint::from(9u32), epsilon.unwrap());
+
+        // Columns where every line agrees are still flipped
+        let input = ["10", "11"];
+        let report = Report::parse(&input, &binary).unwrap();
+        assert_eq!(BigUint::from(0u32), calc_epsilon(&report).unwrap());
+    }
+
+    #[test]
+    fn test_wide_diagnostics() {
+        let binary = Alphabet::base(2).unwrap();
+        let input = [
+            "1000000000000000000000000000000000000000000000000000000000000000000000000001",
+            "1000000000000000000000000000000000000000000000000000000000000000000000000011",
+            "0000000000000000000000000000000000000000000000000000000000000000000000000011",
+        ];
+        let gamma = (BigUint::from(1u32) << 75usize) + 3u32;
+
+        let report = Report::parse(&input, &binary).unwrap();
+        let result = calc_gamma(&report);
+        assert!(result.is_ok());
+        assert_eq!((gamma.clone(), 76), result.unwrap());
+        assert_eq!(
+            (BigUint::from(1u32) << 75usize) - 4u32,
+            calc_epsilon(&report).unwrap()
+        );
+
+        let result = run_sieve(&report, oxygen());
+        assert!(result.is_ok());
+        assert_eq!((BigUint::from(1u32) << 75usize) + 3u32, result.unwrap().0);
+    }
+
+    #[test]
+    fn test_check_width() {
+        assert_eq!(5, check_width(&["00100", "11110"]).unwrap());
+
+        let result = check_width(&["00100", "11110", "1011", "101"]);
+        assert!(result.is_err());
+        assert_eq!(
+            "Line 3 (1011) is 4 symbols wide but line 1 is 5 symbols wide",
+            result.unwrap_err().to_string()
+        );
+
+        let binary = Alphabet::base(2).unwrap();
+        assert!(Report::parse(&["00100", "1011"], &binary).is_err());
+        assert!(Report::parse(&["00100", "10121"], &binary).is_err());
+    }
+
+    #[test]
+    fn test_generated_report() {
+        let binary = Alphabet::base(2).unwrap();
+        let input = generate_report(1_000_000, 32, 2021);
+        let lines = input.split('\n').collect::<Vec<&str>>();
+        let report = Report::parse(&lines, &binary).unwrap();
+
+        let (gamma, _) = calc_gamma(&report).unwrap();
+        assert_eq!(reference_gamma(&lines), gamma);
+        assert_eq!(
+            reference_sieve(&lines, 0, false).unwrap(),
+            run_sieve(&report, oxygen()).unwrap().0
+        );
+        assert_eq!(
+            reference_sieve(&lines, 0, true).unwrap(),
+            run_sieve(&report, co2()).unwrap().0
+        );
+    }
 
-        let epsilon = calc_epsilon(gamma, num_bits);
-        assert_eq!(9, epsilon);
+    #[test]
+    fn test_alphabet() {
+        assert_eq!(
+            Alphabet(vec![
+                '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f'
+            ]),
+            Alphabet::base(16).unwrap()
+        );
+        assert!(Alphabet::base(1).is_err());
+        assert!(Alphabet::base(37).is_err());
+        assert!(Alphabet::new("ACGT").is_ok());
+        assert!(Alphabet::new("ACGA").is_err());
+    }
+
+    #[test]
+    fn test_hex_report() {
+        let hex = Alphabet::base(16).unwrap();
+        let input = ["a1f", "a2f", "b2e", "c2f", "b30"];
+        let report = Report::parse(&input, &hex).unwrap();
+
+        let result = calc_gamma(&report);
+        assert!(result.is_ok());
+        assert_eq!((BigUint::from(0xb2fu32), 3), result.unwrap());
+        let result = calc_epsilon(&report);
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(0x001u32), result.unwrap());
+
+        // Ties go to the highest symbol, b in the first column and 3 in the second
+        let result = run_sieve(&report, oxygen());
+        assert!(result.is_ok());
+        let (value, steps) = result.unwrap();
+        assert_eq!(BigUint::from(0xb30u32), value);
+        assert_eq!(
+            vec!['b', '3'],
+            steps.iter().map(|s| s.kept).collect::<Vec<_>>()
+        );
+
+        // c is least common in the first column
+        let result = run_sieve(&report, co2());
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(0xc2fu32), result.unwrap().0);
+    }
+
+    #[test]
+    fn test_custom_alphabet() {
+        let dna = Alphabet::new("ACGT").unwrap();
+        let input = ["ACG", "ACT", "TCG"];
+        let report = Report::parse(&input, &dna).unwrap();
+
+        let result = calc_gamma(&report);
+        assert!(result.is_ok());
+        assert_eq!((BigUint::from(0b00_01_10u32), 3), result.unwrap());
+
+        let result = run_sieve(&report, co2());
+        assert!(result.is_ok());
+        assert_eq!(BigUint::from(0b11_01_10u32), result.unwrap().0);
+        assert!(Report::parse(&["ACGU"], &dna).is_err());
     }
 }
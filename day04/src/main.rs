@@ -1,6 +1,7 @@
 use anyhow::{anyhow, bail, Result};
 use clap::Parser;
-use std::borrow::BorrowMut;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 use std::str::FromStr;
@@ -12,138 +13,571 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     worst: bool,
+    #[clap(short, long, default_value = "rows,columns")]
+    rules: String,
+    #[clap(short, long)]
+    naive: bool,
+    #[clap(short = 'p', long)]
+    report: bool,
+    #[clap(short = 'g', long)]
+    rig: Option<usize>,
+    #[clap(short, long, default_value = "100")]
+    max_draws: usize,
+    #[clap(short = 'd', long)]
+    min_draws: bool,
 }
 
 #[derive(Eq, Debug, PartialEq)]
-struct BingoBoard([[u8; 5]; 5]);
+struct BingoBoard(Vec<Vec<u32>>);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Pattern {
+    Row(usize),
+    Column(usize),
+    Diagonal,
+    AntiDiagonal,
+    Corners,
+    FullCard,
+    Mask(usize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum WinRule {
+    Rows,
+    Columns,
+    Diagonals,
+    Corners,
+    FullCard,
+    Mask(Vec<Vec<bool>>),
+}
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct WinPattern {
+    pattern: Pattern,
+    cells: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PatternSet {
+    patterns: Vec<WinPattern>,
+    by_cell: Vec<Vec<usize>>,
+}
+
+// Scores multiply a drawn number by the sum of many others, so they get the widest type
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-struct BingoResult(usize, u32);
+struct BingoResult(usize, u128, Pattern);
+
+struct BingoGame<'a> {
+    boards: &'a [BingoBoard],
+    patterns: HashMap<usize, PatternSet>,
+    index: HashMap<u32, Vec<(usize, usize)>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct GameReport {
+    finishes: Vec<(usize, BingoResult)>,
+    never_won: Vec<usize>,
+    in_play: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum RigOutcome {
+    Found(Vec<u32>, BingoResult),
+    ExceedsBound(usize),
+    Impossible,
+}
+
+struct BoardState {
+    marked: Vec<bool>,
+    hits: Vec<usize>,
+    unmarked_sum: u64,
+    finished: bool,
+}
 
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
     File::open(opts.input)?.read_to_string(&mut input)?;
+    let rules = WinRule::parse_all(&opts.rules)?;
     let mut iter = input.split('\n');
     let bingo_numbers = iter
         .next()
         .map(|line| {
             line.split(',')
-                .map(|n| Ok(u8::from_str(n)?))
+                .map(|n| Ok(u32::from_str(n)?))
                 .collect::<Result<Vec<_>>>()
         })
         .unwrap_or_else(|| Err(anyhow!("No input")))?;
     let mut boards = Vec::new();
-    loop {
-        match BingoBoard::new(&mut iter)? {
-            Some(board) => boards.push(board),
-            None => break,
+    while let Some(board) = BingoBoard::new(&mut iter)? {
+        boards.push(board);
+    }
+    if opts.report {
+        let report = BingoGame::new(&boards, &rules)?.report(&bingo_numbers);
+        print_report(&report, &bingo_numbers);
+        return Ok(());
+    }
+    if opts.min_draws {
+        let game = BingoGame::new(&boards, &rules)?;
+        for (board, min_draws) in game.min_draws(&bingo_numbers).iter().enumerate() {
+            match min_draws {
+                Some((draws, pattern)) => {
+                    println!("Board {} needs {} draws ({:?})", board + 1, draws, pattern)
+                }
+                None => println!("Board {} can never get bingo", board + 1),
+            }
+        }
+        return Ok(());
+    }
+    if let Some(target) = opts.rig {
+        let target = target
+            .checked_sub(1)
+            .filter(|target| *target < boards.len())
+            .ok_or_else(|| anyhow!("No board {}", target))?;
+        let game = BingoGame::new(&boards, &rules)?;
+        match game.rig(target, &bingo_numbers, opts.max_draws) {
+            RigOutcome::Found(numbers, result) => println!(
+                "Board {} wins first after {} draws with score of {} ({:?}): {}",
+                target + 1,
+                numbers.len(),
+                result.1,
+                result.2,
+                numbers
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            RigOutcome::ExceedsBound(draws) => println!(
+                "Board {} can't win first within {} draws, it needs {}",
+                target + 1,
+                opts.max_draws,
+                draws
+            ),
+            RigOutcome::Impossible => {
+                println!("Board {} can never win first", target + 1)
+            }
         }
+        return Ok(());
     }
-    let results = boards
-        .iter()
-        .filter_map(|board| board.bingo(bingo_numbers.as_slice()));
+    let results = if opts.naive {
+        boards
+            .iter()
+            .map(|board| {
+                let patterns = PatternSet::new(win_patterns(&rules, board.size())?, board.size());
+                Ok(board.bingo(bingo_numbers.as_slice(), &patterns))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+    } else {
+        BingoGame::new(&boards, &rules)?
+            .play(&bingo_numbers)
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect()
+    };
+    let results = results.into_iter();
     let best = if opts.worst {
         results.max_by_key(|b| b.0)
     } else {
         results.min_by_key(|b| b.0)
     }
     .ok_or_else(|| anyhow!("No boards got bingo"))?;
-    println!("Bingo in {} steps with score of {}", best.0, best.1);
+    println!(
+        "Bingo in {} steps with score of {} ({:?})",
+        best.0, best.1, best.2
+    );
     Ok(())
 }
 
+fn print_report(report: &GameReport, numbers: &[u32]) {
+    println!("Rank\tBoard\tStep\tNumber\tScore\tPattern");
+    for (rank, (board, result)) in report.finishes.iter().enumerate() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{:?}",
+            rank + 1,
+            board + 1,
+            result.0,
+            numbers[result.0],
+            result.1,
+            result.2
+        );
+    }
+    if !report.never_won.is_empty() {
+        println!(
+            "Boards that never got bingo: {}",
+            report
+                .never_won
+                .iter()
+                .map(|board| (board + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    println!("Step\tNumber\tIn play");
+    for (step, (number, in_play)) in numbers.iter().zip(report.in_play.iter()).enumerate() {
+        println!("{}\t{}\t{}", step, number, in_play);
+    }
+}
+
+impl WinRule {
+    fn parse(s: &str) -> Result<WinRule> {
+        match s.trim() {
+            "rows" => Ok(WinRule::Rows),
+            "columns" => Ok(WinRule::Columns),
+            "diagonals" => Ok(WinRule::Diagonals),
+            "corners" => Ok(WinRule::Corners),
+            "full-card" => Ok(WinRule::FullCard),
+            mask => Ok(WinRule::Mask(
+                mask.split('/')
+                    .map(|row| {
+                        row.chars()
+                            .map(|c| match c {
+                                '0' => Ok(false),
+                                '1' => Ok(true),
+                                _ => bail!("Invalid rule {}", mask),
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )),
+        }
+    }
+
+    fn parse_all(s: &str) -> Result<Vec<WinRule>> {
+        s.split(',').map(WinRule::parse).collect()
+    }
+
+    fn patterns(&self, size: usize, mask_index: usize) -> Result<Vec<WinPattern>> {
+        let pattern = |pattern, cells: Vec<usize>| WinPattern { pattern, cells };
+        Ok(match self {
+            WinRule::Rows => (0..size)
+                .map(|row| {
+                    pattern(
+                        Pattern::Row(row),
+                        (0..size).map(|col| row * size + col).collect(),
+                    )
+                })
+                .collect(),
+            WinRule::Columns => (0..size)
+                .map(|col| {
+                    pattern(
+                        Pattern::Column(col),
+                        (0..size).map(|row| row * size + col).collect(),
+                    )
+                })
+                .collect(),
+            WinRule::Diagonals => vec![
+                pattern(Pattern::Diagonal, (0..size).map(|i| i * size + i).collect()),
+                pattern(
+                    Pattern::AntiDiagonal,
+                    (0..size).map(|i| i * size + size - 1 - i).collect(),
+                ),
+            ],
+            WinRule::Corners => {
+                let mut corners = vec![0, size - 1, (size - 1) * size, size * size - 1];
+                corners.dedup();
+                vec![pattern(Pattern::Corners, corners)]
+            }
+            WinRule::FullCard => vec![pattern(Pattern::FullCard, (0..size * size).collect())],
+            WinRule::Mask(mask) => {
+                if mask.len() != size || mask.iter().any(|row| row.len() != size) {
+                    bail!("Mask {} doesn't match board size {}", mask_index, size);
+                }
+                let cells = mask
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .filter(|(_, set)| **set)
+                    .map(|(cell, _)| cell)
+                    .collect::<Vec<_>>();
+                if cells.is_empty() {
+                    bail!("Mask {} is empty", mask_index);
+                }
+                vec![pattern(Pattern::Mask(mask_index), cells)]
+            }
+        })
+    }
+}
+
+impl PatternSet {
+    fn new(patterns: Vec<WinPattern>, size: usize) -> PatternSet {
+        let mut by_cell = vec![Vec::new(); size * size];
+        for (index, pattern) in patterns.iter().enumerate() {
+            for cell in &pattern.cells {
+                by_cell[*cell].push(index);
+            }
+        }
+        PatternSet { patterns, by_cell }
+    }
+}
+
+fn win_patterns(rules: &[WinRule], size: usize) -> Result<Vec<WinPattern>> {
+    let mut patterns = Vec::new();
+    let mut masks = 0;
+    for rule in rules {
+        patterns.extend(rule.patterns(size, masks)?);
+        if let WinRule::Mask(_) = rule {
+            masks += 1;
+        }
+    }
+    Ok(patterns)
+}
+
 impl BingoBoard {
     pub fn new(input: &mut dyn Iterator<Item = &str>) -> Result<Option<BingoBoard>> {
-        let mut board = BingoBoard([[0u8; 5]; 5]);
-        let mut row = 0;
+        let mut rows: Vec<Vec<u32>> = Vec::new();
         loop {
             match input.next() {
-                Some("") => continue,
+                Some("") if rows.is_empty() => continue,
                 Some(line) => {
                     let numbers = line
                         .split(' ')
                         .filter(|n| n != &"")
-                        .map(|n| Ok(u8::from_str(n)?))
+                        .map(|n| Ok(u32::from_str(n)?))
                         .collect::<Result<Vec<_>>>()?;
-                    match numbers.len() {
-                        5 => numbers
-                            .iter()
-                            .enumerate()
-                            .for_each(|(col, n)| board.0[row][col] = *n),
-                        _ => bail!("Invalid line {}", line),
-                    };
-                    if row == 4 {
-                        return Ok(Some(board));
+                    let size = rows.first().unwrap_or(&numbers).len();
+                    if size == 0 || numbers.len() != size {
+                        bail!("Invalid line {}", line);
+                    }
+                    rows.push(numbers);
+                    if rows.len() == size {
+                        return Ok(Some(BingoBoard(rows)));
                     }
                 }
-                None => return Ok(None),
+                None if rows.is_empty() => return Ok(None),
+                None => bail!("Incomplete board with {} rows", rows.len()),
             }
-            row += 1;
         }
     }
 
-    pub fn bingo(&self, numbers: &[u8]) -> Option<BingoResult> {
-        let mut numbers_hit: Vec<u32> = Vec::new();
-        let mut rows = [0u8; 5];
-        let mut cols = [0u8; 5];
+    pub fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn bingo(&self, numbers: &[u32], patterns: &PatternSet) -> Option<BingoResult> {
+        // Numbers drawn again don't mark their cells a second time. Every cell with the drawn
+        // number is marked before the board is scored, and the first completed pattern wins.
+        let mut marked = vec![false; self.size() * self.size()];
+        let mut unmarked_sum = self.sum();
+        let mut hits = vec![0usize; patterns.patterns.len()];
         for (step, number) in numbers.iter().enumerate() {
-            for row in 0..5 {
-                for col in 0..5 {
-                    if self.0[row][col] == *number {
-                        numbers_hit.push(*number as u32);
-                        rows[row] += 1;
-                        cols[col] += 1;
-                        if rows[row] == 5 || cols[col] == 5 {
-                            let board_sum = self.sum();
-                            let hit_sum: u32 = numbers_hit.iter().sum();
-                            let unhit_sum = board_sum - hit_sum;
-                            return Some(BingoResult(step, *number as u32 * unhit_sum));
-                        }
+            let mut completed: Option<usize> = None;
+            for (cell, value) in self.0.iter().flatten().enumerate() {
+                if value != number || marked[cell] {
+                    continue;
+                }
+                marked[cell] = true;
+                unmarked_sum -= *number as u64;
+                for index in &patterns.by_cell[cell] {
+                    hits[*index] += 1;
+                    if hits[*index] == patterns.patterns[*index].cells.len() {
+                        completed = Some(completed.map_or(*index, |c| c.min(*index)));
                     }
                 }
             }
+            if let Some(pattern) = completed {
+                return Some(BingoResult(
+                    step,
+                    *number as u128 * unmarked_sum as u128,
+                    patterns.patterns[pattern].pattern,
+                ));
+            }
         }
         None
     }
 
-    pub fn sum(&self) -> u32 {
+    pub fn sum(&self) -> u64 {
         self.0
             .iter()
             .flat_map(|r| r.iter())
-            .map(|n| *n as u32)
+            .map(|n| *n as u64)
             .sum()
     }
 }
 
+impl<'a> BingoGame<'a> {
+    pub fn new(boards: &'a [BingoBoard], rules: &[WinRule]) -> Result<BingoGame<'a>> {
+        let mut patterns = HashMap::new();
+        let mut index: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+        for (board_index, board) in boards.iter().enumerate() {
+            if let Entry::Vacant(entry) = patterns.entry(board.size()) {
+                entry.insert(PatternSet::new(
+                    win_patterns(rules, board.size())?,
+                    board.size(),
+                ));
+            }
+            for (cell, number) in board.0.iter().flatten().enumerate() {
+                index.entry(*number).or_default().push((board_index, cell));
+            }
+        }
+        Ok(BingoGame {
+            boards,
+            patterns,
+            index,
+        })
+    }
+
+    // Returns the index and result of every board that gets bingo, in the order they finish
+    pub fn play(&self, numbers: &[u32]) -> Vec<(usize, BingoResult)> {
+        let mut states = self
+            .boards
+            .iter()
+            .map(|board| BoardState {
+                marked: vec![false; board.size() * board.size()],
+                hits: vec![0; self.patterns[&board.size()].patterns.len()],
+                unmarked_sum: board.sum(),
+                finished: false,
+            })
+            .collect::<Vec<_>>();
+        let mut results = Vec::new();
+        for (step, number) in numbers.iter().enumerate() {
+            let mut winners = Vec::new();
+            for (board, cell) in self.index.get(number).into_iter().flatten() {
+                let state = &mut states[*board];
+                if state.finished || state.marked[*cell] {
+                    continue;
+                }
+                state.marked[*cell] = true;
+                state.unmarked_sum -= *number as u64;
+                let patterns = &self.patterns[&self.boards[*board].size()];
+                for pattern in &patterns.by_cell[*cell] {
+                    state.hits[*pattern] += 1;
+                    if state.hits[*pattern] == patterns.patterns[*pattern].cells.len() {
+                        winners.push((*board, *pattern));
+                    }
+                }
+            }
+            // Boards finish in index order, using their first completed pattern
+            winners.sort_unstable();
+            winners.dedup_by_key(|(board, _)| *board);
+            for (board, pattern) in winners {
+                let state = &mut states[board];
+                state.finished = true;
+                let pattern = self.patterns[&self.boards[board].size()].patterns[pattern].pattern;
+                results.push((
+                    board,
+                    BingoResult(step, *number as u128 * state.unmarked_sum as u128, pattern),
+                ));
+            }
+        }
+        results
+    }
+
+    // The distinct numbers needed to complete each pattern of a board, if they can all be drawn
+    fn pattern_numbers(&self, board: usize, available: &HashSet<u32>) -> Vec<(Vec<u32>, Pattern)> {
+        let cells = self.boards[board].0.iter().flatten().collect::<Vec<_>>();
+        let mut candidates = self.patterns[&self.boards[board].size()]
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let mut numbers = pattern
+                    .cells
+                    .iter()
+                    .map(|cell| *cells[*cell])
+                    .collect::<Vec<_>>();
+                numbers.sort_unstable();
+                numbers.dedup();
+                (numbers, pattern.pattern)
+            })
+            .filter(|(numbers, _)| numbers.iter().all(|n| available.contains(n)))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(numbers, _)| numbers.len());
+        candidates
+    }
+
+    pub fn min_draws(&self, numbers: &[u32]) -> Vec<Option<(usize, Pattern)>> {
+        let available = numbers.iter().cloned().collect();
+        (0..self.boards.len())
+            .map(|board| {
+                self.pattern_numbers(board, &available)
+                    .first()
+                    .map(|(numbers, pattern)| (numbers.len(), *pattern))
+            })
+            .collect()
+    }
+
+    // Whatever order the numbers are drawn in, the winning pattern of the target is completed by
+    // the last draw. Any other board with a pattern made up of drawn numbers will then have
+    // finished as well, so it's enough to try to draw the numbers of each pattern of the target.
+    pub fn rig(&self, target: usize, numbers: &[u32], max_draws: usize) -> RigOutcome {
+        let available = numbers.iter().cloned().collect();
+        let patterns = &self.patterns[&self.boards[target].size()];
+        for (draws, _) in self.pattern_numbers(target, &available) {
+            let finishes = self.play(&draws);
+            if finishes.len() != 1 || finishes[0].0 != target {
+                continue;
+            }
+            if draws.len() > max_draws {
+                return RigOutcome::ExceedsBound(draws.len());
+            }
+            if let Some(result) = self.boards[target].bingo(&draws, patterns) {
+                return RigOutcome::Found(draws, result);
+            }
+        }
+        RigOutcome::Impossible
+    }
+
+    pub fn report(&self, numbers: &[u32]) -> GameReport {
+        let finishes = self.play(numbers);
+        let mut won = vec![false; self.boards.len()];
+        let mut finished_per_step = vec![0; numbers.len()];
+        for (board, result) in &finishes {
+            won[*board] = true;
+            finished_per_step[result.0] += 1;
+        }
+        let never_won = (0..self.boards.len())
+            .filter(|board| !won[*board])
+            .collect();
+        let in_play = finished_per_step
+            .iter()
+            .scan(self.boards.len(), |in_play, finished| {
+                *in_play -= finished;
+                Some(*in_play)
+            })
+            .collect();
+        GameReport {
+            finishes,
+            never_won,
+            in_play,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{BingoBoard, BingoResult};
+    use crate::{
+        win_patterns, BingoBoard, BingoGame, BingoResult, GameReport, Pattern, PatternSet,
+        RigOutcome, WinRule,
+    };
+    use yare::parameterized;
 
     #[test]
     fn test_new_board() {
         let input = include_str!("test_input");
         let expected = [
-            BingoBoard([
-                [22u8, 13u8, 17u8, 11u8, 0u8],
-                [8u8, 2u8, 23u8, 4u8, 24u8],
-                [21u8, 9u8, 14u8, 16u8, 7u8],
-                [6u8, 10u8, 3u8, 18u8, 5u8],
-                [1u8, 12u8, 20u8, 15u8, 19u8],
+            BingoBoard(vec![
+                vec![22, 13, 17, 11, 0],
+                vec![8, 2, 23, 4, 24],
+                vec![21, 9, 14, 16, 7],
+                vec![6, 10, 3, 18, 5],
+                vec![1, 12, 20, 15, 19],
             ]),
-            BingoBoard([
-                [3u8, 15u8, 0u8, 2u8, 22u8],
-                [9u8, 18u8, 13u8, 17u8, 5u8],
-                [19u8, 8u8, 7u8, 25u8, 23u8],
-                [20u8, 11u8, 10u8, 24u8, 4u8],
-                [14u8, 21u8, 16u8, 12u8, 6u8],
+            BingoBoard(vec![
+                vec![3, 15, 0, 2, 22],
+                vec![9, 18, 13, 17, 5],
+                vec![19, 8, 7, 25, 23],
+                vec![20, 11, 10, 24, 4],
+                vec![14, 21, 16, 12, 6],
             ]),
-            BingoBoard([
-                [14u8, 21u8, 17u8, 24u8, 4u8],
-                [10u8, 16u8, 15u8, 9u8, 19u8],
-                [18u8, 8u8, 23u8, 26u8, 20u8],
-                [22u8, 11u8, 13u8, 6u8, 5u8],
-                [2u8, 0u8, 12u8, 3u8, 7u8],
+            BingoBoard(vec![
+                vec![14, 21, 17, 24, 4],
+                vec![10, 16, 15, 9, 19],
+                vec![18, 8, 23, 26, 20],
+                vec![22, 11, 13, 6, 5],
+                vec![2, 0, 12, 3, 7],
             ]),
         ];
 
@@ -154,57 +588,331 @@ mod test {
             BingoBoard::new(&mut lines),
         ];
 
-        expected
-            .iter()
-            .zip(boards.into_iter())
-            .for_each(|(expected, board)| {
-                assert!(board.is_ok());
-                let board = board.unwrap();
-                assert!(board.is_some());
-                assert_eq!(expected, &board.unwrap());
-            });
+        expected.iter().zip(boards).for_each(|(expected, board)| {
+            assert!(board.is_ok());
+            let board = board.unwrap();
+            assert!(board.is_some());
+            assert_eq!(expected, &board.unwrap());
+        });
     }
 
     #[test]
     fn test_bingo() {
         let boards = [
-            BingoBoard([
-                [22u8, 13u8, 17u8, 11u8, 0u8],
-                [8u8, 2u8, 23u8, 4u8, 24u8],
-                [21u8, 9u8, 14u8, 16u8, 7u8],
-                [6u8, 10u8, 3u8, 18u8, 5u8],
-                [1u8, 12u8, 20u8, 15u8, 19u8],
+            BingoBoard(vec![
+                vec![22, 13, 17, 11, 0],
+                vec![8, 2, 23, 4, 24],
+                vec![21, 9, 14, 16, 7],
+                vec![6, 10, 3, 18, 5],
+                vec![1, 12, 20, 15, 19],
             ]),
-            BingoBoard([
-                [3u8, 15u8, 0u8, 2u8, 22u8],
-                [9u8, 18u8, 13u8, 17u8, 5u8],
-                [19u8, 8u8, 7u8, 25u8, 23u8],
-                [20u8, 11u8, 10u8, 24u8, 4u8],
-                [14u8, 21u8, 16u8, 12u8, 6u8],
+            BingoBoard(vec![
+                vec![3, 15, 0, 2, 22],
+                vec![9, 18, 13, 17, 5],
+                vec![19, 8, 7, 25, 23],
+                vec![20, 11, 10, 24, 4],
+                vec![14, 21, 16, 12, 6],
             ]),
-            BingoBoard([
-                [14u8, 21u8, 17u8, 24u8, 4u8],
-                [10u8, 16u8, 15u8, 9u8, 19u8],
-                [18u8, 8u8, 23u8, 26u8, 20u8],
-                [22u8, 11u8, 13u8, 6u8, 5u8],
-                [2u8, 0u8, 12u8, 3u8, 7u8],
+            BingoBoard(vec![
+                vec![14, 21, 17, 24, 4],
+                vec![10, 16, 15, 9, 19],
+                vec![18, 8, 23, 26, 20],
+                vec![22, 11, 13, 6, 5],
+                vec![2, 0, 12, 3, 7],
             ]),
         ];
         let numbers = [
-            7u8, 4u8, 9u8, 5u8, 11u8, 17u8, 23u8, 2u8, 0u8, 14u8, 21u8, 24u8, 10u8, 16u8, 13u8,
-            6u8, 15u8, 25u8, 12u8, 22u8, 18u8, 20u8, 8u8, 19u8, 3u8, 26u8, 1u8,
+            7, 4, 9, 5, 11, 17, 23, 2, 0, 14, 21, 24, 10, 16, 13, 6, 15, 25, 12, 22, 18, 20, 8, 19,
+            3, 26, 1,
         ];
+        let patterns = PatternSet::new(
+            win_patterns(&[WinRule::Rows, WinRule::Columns], 5).unwrap(),
+            5,
+        );
         let results = boards
             .iter()
-            .filter_map(|board| board.bingo(&numbers))
+            .filter_map(|board| board.bingo(&numbers, &patterns))
             .collect::<Vec<_>>();
         assert_eq!(
             vec![
-                BingoResult(13, 2192),
-                BingoResult(14, 1924),
-                BingoResult(11, 4512)
+                BingoResult(13, 2192, Pattern::Row(2)),
+                BingoResult(14, 1924, Pattern::Column(2)),
+                BingoResult(11, 4512, Pattern::Row(0))
             ],
             results
         );
     }
+
+    #[test]
+    fn test_new_board_sizes() {
+        let input = "\n1 2 3\n4 5 6\n7 8 9\n\n100000 2\n3 4\n\n1 2\n3";
+        let mut lines = input.split('\n');
+
+        let board = BingoBoard::new(&mut lines);
+        assert!(board.is_ok());
+        assert_eq!(
+            Some(BingoBoard(vec![
+                vec![1, 2, 3],
+                vec![4, 5, 6],
+                vec![7, 8, 9]
+            ])),
+            board.unwrap()
+        );
+        let board = BingoBoard::new(&mut lines);
+        assert!(board.is_ok());
+        assert_eq!(
+            Some(BingoBoard(vec![vec![100000, 2], vec![3, 4]])),
+            board.unwrap()
+        );
+        assert!(BingoBoard::new(&mut lines).is_err());
+        assert!(BingoBoard::new(&mut "1 2 3\n4 5".split('\n')).is_err());
+        assert!(BingoBoard::new(&mut "\n".split('\n')).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = WinRule::parse_all("rows,columns,diagonals,corners,full-card,100/010/001");
+        assert!(rules.is_ok());
+        assert_eq!(
+            vec![
+                WinRule::Rows,
+                WinRule::Columns,
+                WinRule::Diagonals,
+                WinRule::Corners,
+                WinRule::FullCard,
+                WinRule::Mask(vec![
+                    vec![true, false, false],
+                    vec![false, true, false],
+                    vec![false, false, true]
+                ]),
+            ],
+            rules.unwrap()
+        );
+        assert!(WinRule::parse_all("rows,diagonal").is_err());
+        assert!(win_patterns(&[WinRule::parse("10/01").unwrap()], 3).is_err());
+        assert!(win_patterns(&[WinRule::parse("00/00").unwrap()], 2).is_err());
+    }
+
+    #[parameterized{
+        diagonal = { "diagonals", 3, 5 * (2 + 4 + 6 + 7 + 8), Pattern::Diagonal },
+        anti_diagonal = { "diagonals", 3, 5 * (1 + 4 + 6 + 8 + 9), Pattern::AntiDiagonal },
+        corners = { "rows,corners", 4, 9 * (2 + 4 + 6 + 8), Pattern::Corners },
+        full_card = { "full-card", 8, 0, Pattern::FullCard },
+        mask = { "010/111/010,rows", 4, 4 * (1 + 3 + 7 + 9), Pattern::Mask(0) },
+    }]
+    fn test_bingo_patterns(rules: &str, step: usize, score: u128, pattern: Pattern) {
+        let board = BingoBoard(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        let numbers = match pattern {
+            Pattern::Diagonal => vec![1, 9, 3, 5],
+            Pattern::AntiDiagonal => vec![3, 7, 2, 5],
+            Pattern::Corners => vec![1, 5, 3, 7, 9],
+            _ => vec![5, 2, 8, 6, 4, 1, 3, 7, 9],
+        };
+        let patterns = PatternSet::new(
+            win_patterns(&WinRule::parse_all(rules).unwrap(), 3).unwrap(),
+            3,
+        );
+
+        assert_eq!(
+            Some(BingoResult(step, score, pattern)),
+            board.bingo(&numbers, &patterns)
+        );
+    }
+
+    fn generate_boards(count: usize, size: usize, numbers: u32, seed: u64) -> Vec<BingoBoard> {
+        let mut state = seed;
+        let mut random = move |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize % bound
+        };
+        (0..count)
+            .map(|_| {
+                let mut pool = (0..numbers).collect::<Vec<_>>();
+                let cells = (0..size * size)
+                    .map(|_| pool.swap_remove(random(pool.len())))
+                    .collect::<Vec<_>>();
+                BingoBoard(cells.chunks(size).map(|row| row.to_vec()).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_play() {
+        let mut lines = include_str!("test_input").split('\n');
+        let boards = [
+            BingoBoard::new(&mut lines).unwrap().unwrap(),
+            BingoBoard::new(&mut lines).unwrap().unwrap(),
+            BingoBoard::new(&mut lines).unwrap().unwrap(),
+        ];
+        let numbers = [
+            7, 4, 9, 5, 11, 17, 23, 2, 0, 14, 21, 24, 10, 16, 13, 6, 15, 25, 12, 22, 18, 20, 8, 19,
+            3, 26, 1,
+        ];
+        let game = BingoGame::new(&boards, &[WinRule::Rows, WinRule::Columns]).unwrap();
+
+        assert_eq!(
+            vec![
+                (2, BingoResult(11, 4512, Pattern::Row(0))),
+                (0, BingoResult(13, 2192, Pattern::Row(2))),
+                (1, BingoResult(14, 1924, Pattern::Column(2))),
+            ],
+            game.play(&numbers)
+        );
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        let boards = [
+            BingoBoard(vec![vec![3000000000, 1000000000], vec![2000000000, 2]]),
+            BingoBoard(
+                (0..5)
+                    .map(|row| (0..5).map(|col| u32::MAX - row * 5 - col).collect())
+                    .collect(),
+            ),
+        ];
+        let rules = [WinRule::Rows, WinRule::Columns];
+        let mut numbers = vec![3000000000, 2, 1000000000];
+        numbers.extend((0..5).map(|n| u32::MAX - n));
+        // The sum of the unmarked numbers times the last one drawn overflows a u64
+        let large_score =
+            (u32::MAX - 4) as u128 * (5..25).map(|n| (u32::MAX - n) as u128).sum::<u128>();
+        let expected = [
+            (0, BingoResult(2, 1000000000 * 2000000000, Pattern::Row(0))),
+            (1, BingoResult(7, large_score, Pattern::Row(0))),
+        ];
+
+        let game = BingoGame::new(&boards, &rules).unwrap();
+        assert_eq!(expected.to_vec(), game.play(&numbers));
+        for (board, result) in expected {
+            let size = boards[board].size();
+            let patterns = PatternSet::new(win_patterns(&rules, size).unwrap(), size);
+            assert_eq!(Some(result), boards[board].bingo(&numbers, &patterns));
+        }
+    }
+
+    #[test]
+    fn test_bingo_duplicate_draw() {
+        let boards = [BingoBoard(vec![vec![1, 2], vec![3, 4]])];
+        let rules = [WinRule::Rows, WinRule::Columns];
+        let patterns = PatternSet::new(win_patterns(&rules, 2).unwrap(), 2);
+
+        // Drawing 1 twice must not complete the first row on its own
+        assert_eq!(None, boards[0].bingo(&[1, 1], &patterns));
+
+        // Both cells with 5 are marked before the board is scored
+        let repeated = [BingoBoard(vec![vec![5, 1], vec![2, 5]])];
+        let expected = BingoResult(1, 5 * 2, Pattern::Row(0));
+        assert_eq!(Some(expected), repeated[0].bingo(&[1, 5], &patterns));
+        let game = BingoGame::new(&repeated, &rules).unwrap();
+        assert_eq!(vec![(0, expected)], game.play(&[1, 5]));
+        let numbers = [4, 4, 4, 2];
+        let expected = BingoResult(3, 2 * 4, Pattern::Column(1));
+        assert_eq!(Some(expected), boards[0].bingo(&numbers, &patterns));
+        let game = BingoGame::new(&boards, &rules).unwrap();
+        assert_eq!(vec![(0, expected)], game.play(&numbers));
+    }
+
+    #[test]
+    fn test_play_matches_naive() {
+        let rules = WinRule::parse_all("rows,columns,diagonals").unwrap();
+        let mut boards = generate_boards(2_000, 5, 100, 2021);
+        // Every third board repeats a number so that one draw marks two cells
+        for (index, board) in boards.iter_mut().enumerate().step_by(3) {
+            board.0[index % 5][(index / 5) % 5] = board.0[4 - index % 5][2];
+        }
+        let numbers = generate_boards(1, 10, 100, 4)[0]
+            .0
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        let patterns = PatternSet::new(win_patterns(&rules, 5).unwrap(), 5);
+
+        let mut expected = boards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, board)| board.bingo(&numbers, &patterns).map(|r| (index, r)))
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|(index, result)| (result.0, *index));
+
+        let game = BingoGame::new(&boards, &rules).unwrap();
+        assert_eq!(expected, game.play(&numbers));
+    }
+
+    #[test]
+    fn test_play_many_boards() {
+        let boards = generate_boards(100_000, 5, 100, 1);
+        let numbers = (0..100).rev().collect::<Vec<_>>();
+
+        let game = BingoGame::new(&boards, &[WinRule::Rows, WinRule::Columns]).unwrap();
+        let results = game.play(&numbers);
+        assert_eq!(100_000, results.len());
+        assert!(results.windows(2).all(|w| w[0].1 .0 <= w[1].1 .0));
+    }
+
+    #[test]
+    fn test_report() {
+        let boards = [
+            BingoBoard(vec![vec![1, 2], vec![3, 4]]),
+            BingoBoard(vec![vec![5, 6], vec![7, 8]]),
+            BingoBoard(vec![vec![1, 9], vec![5, 10]]),
+        ];
+        let numbers = [1, 3, 5, 9, 4];
+        let game = BingoGame::new(&boards, &[WinRule::Rows, WinRule::Columns]).unwrap();
+
+        assert_eq!(
+            GameReport {
+                finishes: vec![
+                    (0, BingoResult(1, 3 * (2 + 4), Pattern::Column(0))),
+                    (2, BingoResult(2, 5 * (9 + 10), Pattern::Column(0))),
+                ],
+                never_won: vec![1],
+                in_play: vec![3, 2, 1, 1, 1],
+            },
+            game.report(&numbers)
+        );
+    }
+
+    #[test]
+    fn test_min_draws() {
+        let boards = [
+            BingoBoard(vec![vec![1, 2], vec![3, 4]]),
+            BingoBoard(vec![vec![5, 5], vec![7, 8]]),
+            BingoBoard(vec![vec![9, 10], vec![11, 12]]),
+        ];
+        let numbers = [1, 2, 3, 4, 5, 7, 9, 12];
+        let game = BingoGame::new(&boards, &[WinRule::Rows, WinRule::Columns]).unwrap();
+
+        assert_eq!(
+            vec![Some((2, Pattern::Row(0))), Some((1, Pattern::Row(0))), None],
+            game.min_draws(&numbers)
+        );
+    }
+
+    #[test]
+    fn test_rig() {
+        let boards = [
+            BingoBoard(vec![vec![1, 2], vec![3, 4]]),
+            BingoBoard(vec![vec![1, 2], vec![5, 6]]),
+            BingoBoard(vec![vec![1, 2], vec![3, 4]]),
+            BingoBoard(vec![vec![1, 7], vec![8, 9]]),
+        ];
+        let numbers = [1, 2, 3, 4, 5, 6, 7, 8];
+        let game = BingoGame::new(&boards, &[WinRule::Rows, WinRule::Columns]).unwrap();
+
+        // The first row is shared with the first board, so the second row has to be drawn
+        assert_eq!(
+            RigOutcome::Found(vec![5, 6], BingoResult(1, 6 * 3, Pattern::Row(1))),
+            game.rig(1, &numbers, 10)
+        );
+        assert_eq!(RigOutcome::ExceedsBound(2), game.rig(1, &numbers, 1));
+        // Identical boards always finish at the same time
+        assert_eq!(RigOutcome::Impossible, game.rig(0, &numbers, 10));
+        assert_eq!(
+            RigOutcome::Found(vec![1, 7], BingoResult(1, 7 * (8 + 9), Pattern::Row(0))),
+            game.rig(3, &numbers, 10)
+        );
+    }
 }
@@ -1,9 +1,10 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
-use itertools::Either;
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser};
+use itertools::{Either, Itertools};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -13,17 +14,73 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     diagonal: bool,
+    #[clap(short, long)]
+    sweep: bool,
+    #[clap(short, long, arg_enum, default_value = "lattice")]
+    rasterization: Rasterization,
+    #[clap(short = 'm', long)]
+    heatmap: Option<String>,
+    #[clap(short, long, arg_enum, default_value = "pgm")]
+    format: HeatmapFormat,
+    #[clap(short, long)]
+    csv: Option<String>,
+    #[clap(short, long)]
+    at: Option<String>,
+    #[clap(short, long)]
+    top: Option<usize>,
+    #[clap(long)]
+    through: Option<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum HeatmapFormat {
+    Pgm,
+    Png,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum Rasterization {
+    Lattice,
+    Bresenham,
 }
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 struct Point {
-    x: u32,
-    y: u32,
+    x: i64,
+    y: i64,
 }
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 struct Line(Point, Point);
 
+// The normal of a line, every line in this direction satisfies a * x + b * y = offset
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct Direction {
+    a: i64,
+    b: i64,
+}
+
+// Coordinates are limited so that the products in the line geometry fit in an i128
+const MAX_COORDINATE: i64 = u32::MAX as i64;
+
+// Larger heatmaps are refused rather than allocated
+const MAX_HEATMAP_SIDE: usize = 8192;
+
+// Grayscale image of the covered area, brighter pixels are covered by more lines
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Heatmap {
+    origin: Point,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Coverage {
+    covered: Vec<(i64, i64)>,
+    overlapping: Vec<(i64, i64)>,
+}
+
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
@@ -33,20 +90,63 @@ fn main() -> Result<()> {
         .split('\n')
         .map(Line::parse)
         .collect::<Result<Vec<_>>>()?;
-    let twice_covered = get_covered_points(&lines, opts.diagonal)
-        .iter()
-        .map(|(_, count)| count)
-        .filter(|count| **count >= 2)
-        .count();
+    let twice_covered = if opts.sweep {
+        if opts.rasterization != Rasterization::Lattice {
+            bail!("Sweeping only supports lattice rasterization");
+        }
+        count_overlapping_points(&lines, opts.diagonal)
+    } else {
+        get_covered_points(&lines, opts.diagonal, opts.rasterization)
+            .values()
+            .filter(|count| **count >= 2)
+            .count()
+    };
     println!("{} points are covered more than twice", twice_covered);
 
+    let queried =
+        opts.heatmap.is_some() || opts.csv.is_some() || opts.at.is_some() || opts.top.is_some();
+    if queried {
+        let covered = get_covered_points(&lines, opts.diagonal, opts.rasterization);
+        if let Some(path) = opts.heatmap {
+            let heatmap = Heatmap::new(&covered)?;
+            let output = match opts.format {
+                HeatmapFormat::Pgm => heatmap.to_pgm(),
+                HeatmapFormat::Png => heatmap.to_png()?,
+            };
+            File::create(path)?.write_all(&output)?;
+        }
+        if let Some(path) = opts.csv {
+            File::create(path)?.write_all(coverage_to_csv(&covered).as_bytes())?;
+        }
+        if let Some(point) = opts.at {
+            let point = Point::parse(&point)?;
+            let count = covered.get(&point).copied().unwrap_or(0);
+            println!("{} is covered by {} lines", point, count);
+        }
+        if let Some(k) = opts.top {
+            for (point, count) in hottest_points(&covered, k) {
+                println!("{} is covered by {} lines", point, count);
+            }
+        }
+    }
+    if let Some(point) = opts.through {
+        let point = Point::parse(&point)?;
+        for (index, line) in segments_through(&lines, &point, opts.diagonal, opts.rasterization) {
+            println!("Line {}: {}", index + 1, line);
+        }
+    }
+
     Ok(())
 }
 
-fn get_covered_points(lines: &[Line], diagonal: bool) -> HashMap<Point, u32> {
+fn get_covered_points(
+    lines: &[Line],
+    diagonal: bool,
+    rasterization: Rasterization,
+) -> HashMap<Point, u32> {
     lines
         .iter()
-        .filter_map(|line| line.get_points(diagonal))
+        .filter_map(|line| line.rasterize(diagonal, rasterization))
         .flatten()
         .fold(HashMap::new(), |mut map, point| {
             match map.get_mut(&point) {
@@ -61,8 +161,270 @@ fn get_covered_points(lines: &[Line], diagonal: bool) -> HashMap<Point, u32> {
         })
 }
 
+fn coverage_to_csv(covered: &HashMap<Point, u32>) -> String {
+    let mut csv = String::from("x,y,count\n");
+    for (point, count) in covered
+        .iter()
+        .sorted_by_key(|(point, _)| (point.y, point.x))
+    {
+        csv.push_str(&format!("{},{},{}\n", point.x, point.y, count));
+    }
+    csv
+}
+
+// Ties are broken by reading order so the result is stable
+fn hottest_points(covered: &HashMap<Point, u32>, k: usize) -> Vec<(Point, u32)> {
+    covered
+        .iter()
+        .map(|(point, count)| (*point, *count))
+        .sorted_by_key(|(point, count)| (std::cmp::Reverse(*count), point.y, point.x))
+        .take(k)
+        .collect()
+}
+
+fn segments_through(
+    lines: &[Line],
+    point: &Point,
+    diagonal: bool,
+    rasterization: Rasterization,
+) -> Vec<(usize, Line)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| {
+            line.rasterize(diagonal, rasterization)
+                .map(|points| points.contains(point))
+                .unwrap_or(false)
+        })
+        .map(|(index, line)| (index, *line))
+        .collect()
+}
+
+impl Heatmap {
+    fn new(covered: &HashMap<Point, u32>) -> Result<Heatmap> {
+        let (min_x, max_x) = covered
+            .keys()
+            .map(|point| point.x)
+            .minmax()
+            .into_option()
+            .unwrap_or((0, -1));
+        let (min_y, max_y) = covered
+            .keys()
+            .map(|point| point.y)
+            .minmax()
+            .into_option()
+            .unwrap_or((0, -1));
+        let side = |min: i64, max: i64| max as i128 - min as i128 + 1;
+        let (width, height) = (side(min_x, max_x), side(min_y, max_y));
+        if width > MAX_HEATMAP_SIDE as i128 || height > MAX_HEATMAP_SIDE as i128 {
+            bail!(
+                "A heatmap of {}x{} pixels is larger than the maximum of {}x{}",
+                width,
+                height,
+                MAX_HEATMAP_SIDE,
+                MAX_HEATMAP_SIDE
+            );
+        }
+        let (width, height) = (width as usize, height as usize);
+        let max_count = covered.values().copied().max().unwrap_or(1) as u64;
+        let mut pixels = vec![0; width * height];
+        for (point, count) in covered {
+            let index = (point.y - min_y) as usize * width + (point.x - min_x) as usize;
+            pixels[index] = (*count as u64 * 255 / max_count) as u8;
+        }
+        Ok(Heatmap {
+            origin: Point::new(min_x, min_y),
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn to_pgm(&self) -> Vec<u8> {
+        let mut pgm = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        pgm.extend_from_slice(&self.pixels);
+        pgm
+    }
+
+    fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&self.pixels)?;
+        }
+        Ok(png)
+    }
+}
+
+fn count_overlapping_points(lines: &[Line], diagonal: bool) -> usize {
+    let mut intervals: HashMap<(Direction, i128), Vec<(i64, i64)>> = HashMap::new();
+    for line in lines {
+        if let Some(direction) = line.direction(diagonal) {
+            let a = direction.position(&line.0);
+            let b = direction.position(&line.1);
+            intervals
+                .entry((direction, direction.offset(&line.0)))
+                .or_default()
+                .push((a.min(b), a.max(b)));
+        }
+    }
+    let coverage = intervals
+        .into_iter()
+        .map(|(key, intervals)| (key, sweep(intervals)))
+        .collect::<Vec<_>>();
+
+    let overlapping: usize = coverage
+        .iter()
+        .flat_map(|(_, coverage)| coverage.overlapping.iter())
+        .map(|(start, end)| (end - start + 1) as usize)
+        .sum();
+
+    // Lines in different directions cross in at most one lattice point
+    let mut crossings: HashMap<(i64, i64), HashSet<usize>> = HashMap::new();
+    for (index_a, ((direction_a, offset_a), coverage_a)) in coverage.iter().enumerate() {
+        for (index_b, ((direction_b, offset_b), coverage_b)) in
+            coverage.iter().enumerate().skip(index_a + 1)
+        {
+            if direction_a == direction_b {
+                continue;
+            }
+            if let Some((x, y)) = direction_a.intersect(*offset_a, *direction_b, *offset_b) {
+                if contains(&coverage_a.covered, direction_a.position_of(x, y))
+                    && contains(&coverage_b.covered, direction_b.position_of(x, y))
+                {
+                    let lines = crossings.entry((x, y)).or_default();
+                    lines.insert(index_a);
+                    lines.insert(index_b);
+                }
+            }
+        }
+    }
+
+    // Crossings have to be counted once, but some of them may already have been counted as part
+    // of the overlaps of one or more of the lines crossing there
+    let counted_overlaps: usize = crossings
+        .iter()
+        .map(|((x, y), lines)| {
+            lines
+                .iter()
+                .filter(|line| {
+                    let ((direction, _), coverage) = &coverage[**line];
+                    contains(&coverage.overlapping, direction.position_of(*x, *y))
+                })
+                .count()
+        })
+        .sum();
+    overlapping + crossings.len() - counted_overlaps
+}
+
+// Sweeps over the inclusive intervals of a single line and returns the merged intervals covered
+// by at least one and at least two of them
+fn sweep(intervals: Vec<(i64, i64)>) -> Coverage {
+    let mut events = intervals
+        .iter()
+        .flat_map(|(start, end)| [(*start, 1), (*end + 1, -1)])
+        .collect::<Vec<_>>();
+    events.sort_unstable();
+    let mut coverage = Coverage::default();
+    let mut count = 0;
+    let mut covered_start = 0;
+    let mut overlapping_start = 0;
+    // Events at the same position are handled at once so that adjacent intervals are merged
+    for (position, events) in &events.into_iter().group_by(|(position, _)| *position) {
+        let previous = count;
+        count += events.map(|(_, delta)| delta).sum::<i32>();
+        if previous == 0 && count > 0 {
+            covered_start = position;
+        } else if previous > 0 && count == 0 {
+            coverage.covered.push((covered_start, position - 1));
+        }
+        if previous < 2 && count >= 2 {
+            overlapping_start = position;
+        } else if previous >= 2 && count < 2 {
+            coverage.overlapping.push((overlapping_start, position - 1));
+        }
+    }
+    coverage
+}
+
+fn contains(intervals: &[(i64, i64)], position: i64) -> bool {
+    let index = intervals.partition_point(|(_, end)| *end < position);
+    intervals
+        .get(index)
+        .map(|(start, _)| *start <= position)
+        .unwrap_or(false)
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Divides and rounds to the nearest integer, rounding halves up
+fn round_div(n: i128, d: i128) -> i128 {
+    let (n, d) = if d < 0 { (-n, -d) } else { (n, d) };
+    (2 * n + d).div_euclid(2 * d)
+}
+
+// How far the minor axis moves after `major` steps along the major axis
+fn rounded_step(major: i64, minor_delta: i64, major_delta: i64) -> i64 {
+    round_div(major as i128 * minor_delta as i128, major_delta as i128) as i64
+}
+
+impl Direction {
+    fn new(dx: i64, dy: i64) -> Direction {
+        let divisor = gcd(dx, dy).max(1);
+        let (a, b) = (-dy / divisor, dx / divisor);
+        if a < 0 || (a == 0 && b < 0) {
+            Direction { a: -a, b: -b }
+        } else {
+            Direction { a, b }
+        }
+    }
+
+    fn offset(&self, point: &Point) -> i128 {
+        self.a as i128 * point.x as i128 + self.b as i128 * point.y as i128
+    }
+
+    fn position(&self, point: &Point) -> i64 {
+        self.position_of(point.x, point.y)
+    }
+
+    // The index of a lattice point along a line. Lattice points on the same line are spaced
+    // (b, -a) apart, so this yields consecutive integers for consecutive points.
+    fn position_of(&self, x: i64, y: i64) -> i64 {
+        let (a, b) = (self.a as i128, self.b as i128);
+        (b * x as i128 - a * y as i128).div_euclid(a * a + b * b) as i64
+    }
+
+    // Nearly parallel lines can cross far outside of the coordinate range, crossings that don't
+    // fit an i64 can't be on any segment
+    fn intersect(&self, offset: i128, other: Direction, other_offset: i128) -> Option<(i64, i64)> {
+        let (a1, b1) = (self.a as i128, self.b as i128);
+        let (a2, b2) = (other.a as i128, other.b as i128);
+        let determinant = a1 * b2 - a2 * b1;
+        if determinant == 0 {
+            return None;
+        }
+        let x = offset * b2 - other_offset * b1;
+        let y = a1 * other_offset - a2 * offset;
+        if x % determinant != 0 || y % determinant != 0 {
+            return None;
+        }
+        Some((
+            i64::try_from(x / determinant).ok()?,
+            i64::try_from(y / determinant).ok()?,
+        ))
+    }
+}
+
 impl Point {
-    fn new(x: u32, y: u32) -> Point {
+    fn new(x: i64, y: i64) -> Point {
         Point { x, y }
     }
 
@@ -70,8 +432,20 @@ impl Point {
         let coords = s
             .split(',')
             .map(|p| p.trim())
-            .map(|n| Ok(u32::from_str(n)?))
+            .map(|n| Ok(i64::from_str(n)?))
             .collect::<Result<Vec<_>>>()?;
+        if let Some(coord) = coords
+            .iter()
+            .find(|c| !(-MAX_COORDINATE..=MAX_COORDINATE).contains(*c))
+        {
+            bail!(
+                "Coordinate {} of point {} is outside of -{} to {}",
+                coord,
+                s.trim(),
+                MAX_COORDINATE,
+                MAX_COORDINATE
+            );
+        }
         match coords.as_slice() {
             [x, y] => Ok(Point::new(*x, *y)),
             _ => Err(anyhow!("Invalid point {}", s)),
@@ -79,6 +453,18 @@ impl Point {
     }
 }
 
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.0, self.1)
+    }
+}
+
 impl Line {
     fn parse(s: &str) -> Result<Line> {
         let parts = s.split("->").collect::<Vec<_>>();
@@ -88,33 +474,67 @@ impl Line {
         }
     }
 
+    fn is_axis_aligned(&self) -> bool {
+        self.0.x == self.1.x || self.0.y == self.1.y
+    }
+
+    fn direction(&self, diagonal: bool) -> Option<Direction> {
+        if !diagonal && !self.is_axis_aligned() {
+            return None;
+        }
+        match (self.1.x - self.0.x, self.1.y - self.0.y) {
+            (0, 0) => Some(Direction::new(1, 0)),
+            (dx, dy) => Some(Direction::new(dx, dy)),
+        }
+    }
+
+    fn rasterize(&self, diagonal: bool, rasterization: Rasterization) -> Option<Vec<Point>> {
+        match rasterization {
+            Rasterization::Lattice => self.get_points(diagonal),
+            Rasterization::Bresenham => self.get_bresenham_points(diagonal),
+        }
+    }
+
+    // All points with integer coordinates that lie exactly on the line
     fn get_points(&self, diagonal: bool) -> Option<Vec<Point>> {
-        if self.0.x == self.1.x {
-            Some(
-                get_range_inclusive(self.0.y, self.1.y)
-                    .map(|y| Point::new(self.0.x, y))
-                    .collect(),
-            )
-        } else if self.0.y == self.1.y {
-            Some(
-                get_range_inclusive(self.0.x, self.1.x)
-                    .map(|x| Point::new(x, self.0.y))
-                    .collect(),
-            )
-        } else if diagonal {
-            Some(
-                get_range_inclusive(self.0.x, self.1.x)
-                    .zip(get_range_inclusive(self.0.y, self.1.y))
-                    .map(|(x, y)| Point::new(x, y))
-                    .collect(),
-            )
-        } else {
-            None
+        if !diagonal && !self.is_axis_aligned() {
+            return None;
+        }
+        let dx = self.1.x - self.0.x;
+        let dy = self.1.y - self.0.y;
+        let steps = gcd(dx, dy);
+        if steps == 0 {
+            return Some(vec![self.0]);
         }
+        Some(
+            (0..=steps)
+                .map(|step| Point::new(self.0.x + dx / steps * step, self.0.y + dy / steps * step))
+                .collect(),
+        )
+    }
+
+    // One point per step along the major axis, rounded to the closest point on the minor axis
+    fn get_bresenham_points(&self, diagonal: bool) -> Option<Vec<Point>> {
+        if !diagonal && !self.is_axis_aligned() {
+            return None;
+        }
+        let dx = self.1.x - self.0.x;
+        let dy = self.1.y - self.0.y;
+        Some(if dx == 0 && dy == 0 {
+            vec![self.0]
+        } else if dx.abs() >= dy.abs() {
+            get_range_inclusive(self.0.x, self.1.x)
+                .map(|x| Point::new(x, self.0.y + rounded_step(x - self.0.x, dy, dx)))
+                .collect()
+        } else {
+            get_range_inclusive(self.0.y, self.1.y)
+                .map(|y| Point::new(self.0.x + rounded_step(y - self.0.y, dx, dy), y))
+                .collect()
+        })
     }
 }
 
-fn get_range_inclusive(a: u32, b: u32) -> impl Iterator<Item = u32> {
+fn get_range_inclusive(a: i64, b: i64) -> impl Iterator<Item = i64> {
     if b >= a {
         Either::Left(a..=b)
     } else {
@@ -124,16 +544,33 @@ fn get_range_inclusive(a: u32, b: u32) -> impl Iterator<Item = u32> {
 
 #[cfg(test)]
 mod test {
-    use crate::{get_covered_points, get_range_inclusive, Line, Point};
+    use crate::{
+        count_overlapping_points, coverage_to_csv, get_covered_points, get_range_inclusive,
+        hottest_points, segments_through, sweep, Coverage, Heatmap, Line, Point, Rasterization,
+        MAX_COORDINATE,
+    };
     use maplit::hashmap;
     use yare::parameterized;
 
+    // The generated tests draw their lines from a seeded linear congruential generator
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, bound: u32) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) as u32 % bound
+        }
+    }
+
     #[parameterized{
         forward = {0, 2, vec![0, 1, 2]},
         reverse = {2, 0, vec![2, 1, 0]},
         single = {0, 0, vec![0]},
     }]
-    fn test_get_range_inclusive(a: u32, b: u32, expected: Vec<u32>) {
+    fn test_get_range_inclusive(a: i64, b: i64, expected: Vec<i64>) {
         let actual = get_range_inclusive(a, b).collect::<Vec<_>>();
         assert_eq!(expected, actual);
     }
@@ -141,13 +578,19 @@ mod test {
     #[parameterized{
     ok1 = { "5,6 -> 5,6", Some((5, 6, 5, 6)) },
     ok2 = { "5,6 -> 5,6", Some((5, 6, 5, 6)) },
+    negative = { "-5,6 -> 5,-6", Some((-5, 6, 5, -6)) },
+    largest = { "4294967295,0 -> -4294967295,0", Some((4294967295, 0, -4294967295, 0)) },
+    billion = { "1000000000,0 -> 1000000000,5", Some((1000000000, 0, 1000000000, 5)) },
+    just_too_large = { "4294967296,0 -> 0,0", None },
+    too_large = { "9223372036854775807,0 -> 0,0", None },
+    too_small = { "0,0 -> 0,-9223372036854775808", None },
     bad1 = { "5,", None },
     bad2 = { ",", None },
     bad3 = { ",6", None },
     bad4 = { "", None },
     bad5 = { "foo", None },
     }]
-    fn test_parse_line(source: &str, expected: Option<(u32, u32, u32, u32)>) {
+    fn test_parse_line(source: &str, expected: Option<(i64, i64, i64, i64)>) {
         let result = Line::parse(source);
         match expected {
             Some((ax, ay, bx, by)) => {
@@ -166,14 +609,15 @@ mod test {
         ok2 = { "5 ,6", Some((5, 6)) },
         ok3 = { "5 , 6", Some((5, 6)) },
         ok4 = { "5, 6", Some((5, 6)) },
+        negative = { "-5, -6", Some((-5, -6)) },
         bad1 = { "5,", None },
         bad2 = { ",", None },
         bad3 = { ",6", None },
         bad4 = { "", None },
         bad5 = { "foo", None },
     }]
-    fn test_parse_point(source: &str, expected: Option<(u32, u32)>) {
-        let result = Point::parse(&source);
+    fn test_parse_point(source: &str, expected: Option<(i64, i64)>) {
+        let result = Point::parse(source);
         match expected {
             Some((x, y)) => {
                 assert!(result.is_ok());
@@ -272,7 +716,258 @@ mod test {
             Point::new(2, 5) => 1,
         };
 
-        let covered = get_covered_points(&lines, false);
+        let covered = get_covered_points(&lines, false, Rasterization::Lattice);
         assert_eq!(expected, covered);
     }
+
+    fn count_rasterized(lines: &[Line], diagonal: bool) -> usize {
+        get_covered_points(lines, diagonal, Rasterization::Lattice)
+            .values()
+            .filter(|count| **count >= 2)
+            .count()
+    }
+
+    #[test]
+    fn test_sweep() {
+        let coverage = sweep(vec![(0, 5), (3, 8), (4, 4), (10, 12), (13, 13)]);
+        assert_eq!(
+            Coverage {
+                covered: vec![(0, 8), (10, 13)],
+                overlapping: vec![(3, 5)],
+            },
+            coverage
+        );
+    }
+
+    #[parameterized{
+        non_diagonal = { false, 5 },
+        diagonal = { true, 12 },
+    }]
+    fn test_count_overlapping_points(diagonal: bool, expected: usize) {
+        let lines = sample_lines();
+
+        assert_eq!(expected, count_rasterized(&lines, diagonal));
+        assert_eq!(expected, count_overlapping_points(&lines, diagonal));
+    }
+
+    #[parameterized{
+        seed1 = { 1 },
+        seed2 = { 2021 },
+        seed3 = { 31337 },
+    }]
+    fn test_count_overlapping_points_generated(seed: u64) {
+        let mut random = Lcg(seed);
+        let lines = (0..500)
+            .map(|_| {
+                let start =
+                    Point::new(random.below(100) as i64 + 20, random.below(100) as i64 + 20);
+                let length = random.below(20) as i64;
+                let end = match random.below(4) {
+                    0 => Point::new(start.x + length, start.y),
+                    1 => Point::new(start.x, start.y - length),
+                    2 => Point::new(start.x - length, start.y - length),
+                    _ => Point::new(start.x + length, start.y - length),
+                };
+                Line(start, end)
+            })
+            .collect::<Vec<_>>();
+
+        for diagonal in [false, true] {
+            assert_eq!(
+                count_rasterized(&lines, diagonal),
+                count_overlapping_points(&lines, diagonal)
+            );
+        }
+    }
+
+    #[parameterized{
+        slope = { Line(Point::new(0, 0), Point::new(4, 2)), vec![(0, 0), (2, 1), (4, 2)] },
+        steep = { Line(Point::new(1, -1), Point::new(-1, 5)), vec![(1, -1), (0, 2), (-1, 5)] },
+        coprime = { Line(Point::new(0, 0), Point::new(3, 2)), vec![(0, 0), (3, 2)] },
+        point = { Line(Point::new(-3, -3), Point::new(-3, -3)), vec![(-3, -3)] },
+    }]
+    fn test_get_line_points_any_angle(line: Line, expected: Vec<(i64, i64)>) {
+        let expected = expected
+            .into_iter()
+            .map(|(x, y)| Point::new(x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(Some(expected.clone()), line.get_points(true));
+        let axis_aligned = Some(expected).filter(|_| line.is_axis_aligned());
+        assert_eq!(axis_aligned, line.get_points(false));
+    }
+
+    #[parameterized{
+        slope = { Line(Point::new(0, 0), Point::new(4, 2)), vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)] },
+        steep = { Line(Point::new(0, 0), Point::new(-1, -3)), vec![(0, 0), (0, -1), (-1, -2), (-1, -3)] },
+        diagonal = { Line(Point::new(0, 5), Point::new(2, 3)), vec![(0, 5), (1, 4), (2, 3)] },
+        horizontal = { Line(Point::new(-1, 5), Point::new(1, 5)), vec![(-1, 5), (0, 5), (1, 5)] },
+        right_to_left = { Line(Point::new(4, 2), Point::new(0, 0)), vec![(4, 2), (3, 2), (2, 1), (1, 1), (0, 0)] },
+        point = { Line(Point::new(2, -2), Point::new(2, -2)), vec![(2, -2)] },
+    }]
+    fn test_get_bresenham_points(line: Line, expected: Vec<(i64, i64)>) {
+        let expected = expected
+            .into_iter()
+            .map(|(x, y)| Point::new(x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Some(expected),
+            line.rasterize(true, Rasterization::Bresenham)
+        );
+    }
+
+    #[parameterized{
+        shallow = { Line(Point::new(0, 0), Point::new(4, 2)) },
+        shallow_down = { Line(Point::new(-3, 7), Point::new(4, 2)) },
+        steep = { Line(Point::new(1, -5), Point::new(-2, 6)) },
+        diagonal = { Line(Point::new(0, 5), Point::new(5, 0)) },
+        vertical = { Line(Point::new(3, 3), Point::new(3, -3)) },
+        point = { Line(Point::new(1, 1), Point::new(1, 1)) },
+    }]
+    fn test_bresenham_reversed(line: Line) {
+        let reversed = Line(line.1, line.0);
+        let mut points = line.rasterize(true, Rasterization::Bresenham).unwrap();
+        points.reverse();
+        assert_eq!(
+            Some(points),
+            reversed.rasterize(true, Rasterization::Bresenham)
+        );
+
+        let covered = get_covered_points(&[line, reversed], true, Rasterization::Bresenham);
+        assert!(covered.values().all(|count| *count == 2));
+    }
+
+    #[test]
+    fn test_count_overlapping_points_any_angle() {
+        let mut random = Lcg(42);
+        let mut coordinate = move || random.below(41) as i64 - 20;
+        let lines = (0..300)
+            .map(|_| {
+                Line(
+                    Point::new(coordinate(), coordinate()),
+                    Point::new(coordinate(), coordinate()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for diagonal in [false, true] {
+            assert_eq!(
+                count_rasterized(&lines, diagonal),
+                count_overlapping_points(&lines, diagonal)
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_overlapping_points_extreme_coordinates() {
+        let m = MAX_COORDINATE;
+        let lines = [
+            Line(Point::new(-m, -m), Point::new(m, m)),
+            Line(Point::new(-m, m), Point::new(m, -m)),
+            // Nearly vertical and horizontal lines at the edge have offsets around m * m
+            Line(Point::new(m, -m), Point::new(m - 1, m)),
+            Line(Point::new(-m, m), Point::new(m, m - 1)),
+        ];
+        assert_eq!(3, count_overlapping_points(&lines, true));
+    }
+
+    fn sample_lines() -> Vec<Line> {
+        [
+            "0,9 -> 5,9",
+            "8,0 -> 0,8",
+            "9,4 -> 3,4",
+            "2,2 -> 2,1",
+            "7,0 -> 7,4",
+            "6,4 -> 2,0",
+            "0,9 -> 2,9",
+            "3,4 -> 1,4",
+            "0,0 -> 8,8",
+            "5,5 -> 8,2",
+        ]
+        .iter()
+        .map(|line| Line::parse(line).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let covered = hashmap! {
+            Point::new(-1, 2) => 1,
+            Point::new(1, 2) => 2,
+            Point::new(1, 3) => 4,
+        };
+        let heatmap = Heatmap::new(&covered).unwrap();
+
+        assert_eq!(Point::new(-1, 2), heatmap.origin);
+        assert_eq!(vec![63, 0, 127, 0, 0, 255], heatmap.pixels);
+        let mut expected = b"P5\n3 2\n255\n".to_vec();
+        expected.extend_from_slice(&heatmap.pixels);
+        assert_eq!(expected, heatmap.to_pgm());
+        assert!(heatmap.to_png().unwrap().starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[parameterized{
+        wide = { (0, 0), (8192, 0) },
+        tall = { (5, -4096), (5, 4096) },
+        extreme = { (i64::MIN, i64::MIN), (i64::MAX, i64::MAX) },
+    }]
+    fn test_heatmap_too_large(a: (i64, i64), b: (i64, i64)) {
+        let covered = hashmap! {
+            Point::new(a.0, a.1) => 1,
+            Point::new(b.0, b.1) => 1,
+        };
+        assert!(Heatmap::new(&covered).is_err());
+    }
+
+    #[test]
+    fn test_coverage_to_csv() {
+        let covered = hashmap! {
+            Point::new(3, 1) => 1,
+            Point::new(-2, 4) => 3,
+            Point::new(0, 1) => 2,
+        };
+
+        assert_eq!(
+            "x,y,count\n0,1,2\n3,1,1\n-2,4,3\n",
+            coverage_to_csv(&covered)
+        );
+    }
+
+    #[test]
+    fn test_hottest_points() {
+        let covered = get_covered_points(&sample_lines(), true, Rasterization::Lattice);
+
+        assert_eq!(
+            vec![
+                (Point::new(4, 4), 3),
+                (Point::new(6, 4), 3),
+                (Point::new(7, 1), 2),
+            ],
+            hottest_points(&covered, 3)
+        );
+        assert_eq!(covered.len(), hottest_points(&covered, 1000).len());
+    }
+
+    #[parameterized{
+        crossing = { (4, 4), true, vec![1, 2, 8] },
+        axis_only = { (4, 4), false, vec![2] },
+        corner = { (7, 4), true, vec![2, 4] },
+        empty = { (9, 9), true, vec![] },
+    }]
+    fn test_segments_through(point: (i64, i64), diagonal: bool, expected: Vec<usize>) {
+        let lines = sample_lines();
+        let through = segments_through(
+            &lines,
+            &Point::new(point.0, point.1),
+            diagonal,
+            Rasterization::Lattice,
+        );
+
+        assert_eq!(
+            expected,
+            through.iter().map(|(index, _)| *index).collect::<Vec<_>>()
+        );
+        for (index, line) in through {
+            assert_eq!(lines[index], line);
+        }
+    }
 }
@@ -1,8 +1,12 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
 use std::collections::HashMap;
+use std::f64::consts::LN_10;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -12,35 +16,208 @@ struct Opts {
     input: String,
     #[clap(short, long, default_value = "80")]
     days: u32,
+    #[clap(
+        short,
+        long,
+        conflicts_with_all = &["config", "lifespan", "capacity", "modulus", "series", "chart", "growth"]
+    )]
+    naive: bool,
+    #[clap(short, long)]
+    modulus: Option<u64>,
+    #[clap(short, long)]
+    config: Option<String>,
+    #[clap(long, conflicts_with = "config")]
+    cycle: Option<u32>,
+    #[clap(long, conflicts_with = "config")]
+    first_spawn: Option<u32>,
+    #[clap(long, conflicts_with = "config")]
+    lifespan: Option<u32>,
+    #[clap(long)]
+    capacity: Option<u64>,
+    #[clap(short, long)]
+    series: Option<String>,
+    #[clap(short, long, arg_enum, default_value = "csv")]
+    format: SeriesFormat,
+    #[clap(long)]
+    chart: Option<String>,
+    #[clap(short, long)]
+    growth: bool,
 }
 
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum SeriesFormat {
+    Csv,
+    Json,
+}
+
+// A species spawns every `cycle` days, newborns need `first_spawn` days to spawn their first fish
+// and, if there's a lifespan, fish die after spawning that many times
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct Species {
+    name: String,
+    cycle: u32,
+    first_spawn: u32,
+    lifespan: Option<u32>,
+    initial: Option<Vec<u32>>,
+}
+
+// The carrying capacity limits the total population of all species
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    capacity: Option<u64>,
+    species: Vec<Species>,
+}
+
+// The number of fish per spawned cycles and timer value, stored as spawned * timers + timer
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Population(Vec<BigUint>);
+
+// The buckets the fish of every bucket move to from one day to the next, which is at most two:
+// the next timer, or the newborns and the next spawn count when they spawn
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Transition(Vec<Vec<usize>>);
+
+// Square matrix form of a transition, used to skip ahead many days at once
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Matrix(Vec<Vec<BigUint>>);
+
+struct Simulation {
+    species: Vec<Species>,
+    transitions: Vec<Transition>,
+    capacity: Option<BigUint>,
+    populations: Vec<Population>,
+}
+
+// The populations of every species for every simulated day, starting with the initial one
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Series {
+    names: Vec<String>,
+    timers: Vec<usize>,
+    days: Vec<Vec<Population>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DayRecord {
+    day: usize,
+    total: Number,
+    species: Vec<SpeciesRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpeciesRecord {
+    name: String,
+    total: Number,
+    timers: Vec<Number>,
+}
+
+// Projecting this many days is plenty for the population distribution to settle
+const GROWTH_DAYS: usize = 1000;
+
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
     File::open(opts.input)?.read_to_string(&mut input)?;
     let starts = input
-        .split(",")
+        .split(',')
         .map(|n| Ok(u32::from_str(n.trim())?))
         .collect::<Result<Vec<_>>>()?;
-    let fish = count_fishes(&starts, opts.days);
+
+    // The species of the command line are only used without a configuration
+    let mut config = match opts.config {
+        Some(path) => {
+            let mut config = String::new();
+            File::open(path)?.read_to_string(&mut config)?;
+            Config::parse(&config)?
+        }
+        None => {
+            let default = Species::default();
+            let species = Species {
+                cycle: opts.cycle.unwrap_or(default.cycle),
+                first_spawn: opts.first_spawn.unwrap_or(default.first_spawn),
+                lifespan: opts.lifespan,
+                ..default
+            };
+            species.validate()?;
+            Config {
+                capacity: None,
+                species: vec![species],
+            }
+        }
+    };
+    if opts.naive {
+        let species = &config.species[0];
+        let fish = count_fishes(&starts, opts.days, species.cycle, species.first_spawn);
+        println!("After {} days there will be {} fish", opts.days, fish);
+        return Ok(());
+    }
+    config.capacity = opts.capacity.or(config.capacity);
+    let modulus = opts
+        .modulus
+        .map(|modulus| {
+            if modulus == 0 {
+                Err(anyhow!("The modulus must be positive"))
+            } else {
+                Ok(BigUint::from(modulus))
+            }
+        })
+        .transpose()?;
+
+    let mut simulation = Simulation::new(config, &starts)?;
+    // The projection ignores the carrying capacity, with one only the simulated days are fitted
+    let projected_growth = (opts.growth && simulation.capacity.is_none())
+        .then(|| fit_growth_rate(&simulation.projected_log_totals(GROWTH_DAYS)));
+    let simulate = opts.series.is_some() || opts.chart.is_some() || simulation.capacity.is_some();
+    let (fish, growth) = if simulate {
+        if modulus.is_some() {
+            bail!("The modulus can't be combined with a carrying capacity or a time series");
+        }
+        let series = simulation.run(opts.days);
+        if let Some(path) = opts.series {
+            let output = match opts.format {
+                SeriesFormat::Csv => series.to_csv(),
+                SeriesFormat::Json => series.to_json()?,
+            };
+            File::create(path)?.write_all(output.as_bytes())?;
+        }
+        if let Some(path) = opts.chart {
+            File::create(path)?.write_all(render_growth_chart(&series).as_bytes())?;
+        }
+        let fish = series.totals(opts.days as usize).into_iter().sum();
+        let growth = projected_growth.unwrap_or_else(|| fit_growth_rate(&series.log_totals()));
+        (fish, growth)
+    } else {
+        let fish = simulation.count(opts.days, modulus.as_ref());
+        (fish, projected_growth.flatten())
+    };
     println!("After {} days there will be {} fish", opts.days, fish);
+    if opts.growth {
+        match growth {
+            Some(growth) => println!("The population grows by a factor of {:.6} per day", growth),
+            None => println!("Too few days to fit a growth rate"),
+        }
+    }
     Ok(())
 }
 
-fn count_fishes(starts: &[u32], days: u32) -> u64 {
-    starts.iter().map(|s| count_fish(*s, days)).sum()
+fn count_fishes(starts: &[u32], days: u32, cycle: u32, first_spawn: u32) -> u64 {
+    starts
+        .iter()
+        .map(|s| count_fish(*s, days, cycle, first_spawn))
+        .sum()
 }
 
-fn count_fish(start: u32, days: u32) -> u64 {
+fn count_fish(start: u32, days: u32, cycle: u32, first_spawn: u32) -> u64 {
     let mut production_days = (start..days)
-        .step_by(7)
+        .step_by(cycle as usize)
         .map(|day| (day, 1))
         .collect::<HashMap<_, _>>();
     let mut sum = 1;
-    for day in (start..days) {
+    for day in start..days {
         if let Some(amount) = production_days.get(&day) {
             let amount = *amount;
-            for production_day in ((day + 9)..days).step_by(7) {
+            for production_day in ((day + first_spawn)..days).step_by(cycle as usize) {
                 match production_days.get_mut(&production_day) {
                     Some(existing_amount) => {
                         *existing_amount += amount;
@@ -56,11 +233,589 @@ fn count_fish(start: u32, days: u32) -> u64 {
     sum
 }
 
+// Natural logarithm of numbers that may be too large for a f64
+fn ln(value: &BigUint) -> f64 {
+    let shift = value.bits().saturating_sub(64);
+    let leading = u64::try_from(value >> shift).unwrap_or(u64::MAX) as f64;
+    leading.ln() + shift as f64 * std::f64::consts::LN_2
+}
+
+fn to_number(value: &BigUint) -> Number {
+    Number::from_str(&value.to_string()).expect("Integers are valid JSON numbers")
+}
+
+// Least squares fit of the logarithm of the totals over the second half of the days, the first
+// half is skipped to give the population distribution time to settle
+fn fit_growth_rate(log_totals: &[f64]) -> Option<f64> {
+    let points = log_totals
+        .iter()
+        .enumerate()
+        .skip(log_totals.len() / 2)
+        .map(|(day, total)| (day as f64, *total))
+        .collect::<Vec<_>>();
+    if points.len() < 2 {
+        return None;
+    }
+    if points.iter().any(|(_, total)| total.is_infinite()) {
+        // The population has died out
+        return Some(0.0);
+    }
+    let count = points.len() as f64;
+    let mean_day = points.iter().map(|(day, _)| day).sum::<f64>() / count;
+    let mean_total = points.iter().map(|(_, total)| total).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(day, total)| (day - mean_day) * (total - mean_total))
+        .sum();
+    let variance: f64 = points.iter().map(|(day, _)| (day - mean_day).powi(2)).sum();
+    Some((covariance / variance).exp())
+}
+
+fn reduce(value: BigUint, modulus: Option<&BigUint>) -> BigUint {
+    match modulus {
+        Some(modulus) => value % modulus,
+        None => value,
+    }
+}
+
+impl Default for Species {
+    fn default() -> Species {
+        Species {
+            name: "lanternfish".to_string(),
+            cycle: 7,
+            first_spawn: 9,
+            lifespan: None,
+            initial: None,
+        }
+    }
+}
+
+impl Species {
+    fn validate(&self) -> Result<()> {
+        if self.cycle == 0 || self.first_spawn == 0 {
+            bail!("{} must have a positive cycle and first spawn", self.name);
+        }
+        if self.lifespan == Some(0) {
+            bail!("{} must have a positive lifespan", self.name);
+        }
+        Ok(())
+    }
+
+    fn timers(&self) -> usize {
+        self.cycle.max(self.first_spawn) as usize
+    }
+
+    // Fish without a lifespan don't need to keep track of how many times they've spawned
+    fn spawn_counts(&self) -> usize {
+        self.lifespan.unwrap_or(1) as usize
+    }
+
+    fn newborn(&self) -> usize {
+        self.first_spawn as usize - 1
+    }
+
+    fn population(&self, starts: &[u32]) -> Result<Population> {
+        let mut buckets = vec![BigUint::default(); self.spawn_counts() * self.timers()];
+        for start in starts {
+            let bucket = buckets
+                .get_mut(*start as usize)
+                .filter(|_| (*start as usize) < self.timers())
+                .ok_or_else(|| anyhow!("Invalid timer {} for {}", start, self.name))?;
+            *bucket += 1u32;
+        }
+        Ok(Population(buckets))
+    }
+
+    fn births(&self, population: &Population) -> BigUint {
+        population.0.iter().step_by(self.timers()).sum()
+    }
+
+    fn transition(&self) -> Transition {
+        let timers = self.timers();
+        let spawn_counts = self.spawn_counts();
+        let mut targets = vec![Vec::new(); spawn_counts * timers];
+        for spawned in 0..spawn_counts {
+            let from = spawned * timers;
+            for timer in 1..timers {
+                targets[from + timer].push(from + timer - 1);
+            }
+            targets[from].push(self.newborn());
+            match self.lifespan {
+                None => targets[from].push(self.cycle as usize - 1),
+                Some(lifespan) if spawned + 1 < lifespan as usize => {
+                    targets[from].push(from + timers + self.cycle as usize - 1)
+                }
+                // The fish dies after spawning for the last time
+                Some(_) => {}
+            }
+        }
+        Transition(targets)
+    }
+}
+
+impl Config {
+    fn parse(source: &str) -> Result<Config> {
+        let config: Config = serde_json::from_str(source)?;
+        if config.species.is_empty() {
+            bail!("At least one species is required");
+        }
+        Ok(config)
+    }
+}
+
+impl Simulation {
+    // Species without initial timers start with the timers from the input
+    fn new(config: Config, starts: &[u32]) -> Result<Simulation> {
+        let populations = config
+            .species
+            .iter()
+            .map(|species| {
+                species.validate()?;
+                species.population(species.initial.as_deref().unwrap_or(starts))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Simulation {
+            transitions: config.species.iter().map(Species::transition).collect(),
+            species: config.species,
+            capacity: config.capacity.map(BigUint::from),
+            populations,
+        })
+    }
+
+    fn count(&self, days: u32, modulus: Option<&BigUint>) -> BigUint {
+        let total = self
+            .transitions
+            .iter()
+            .zip(self.populations.iter())
+            .map(|(transition, population)| {
+                transition
+                    .advance(population, days as u64, modulus)
+                    .total(modulus)
+            })
+            .sum();
+        reduce(total, modulus)
+    }
+
+    // Projects the population without carrying capacity using floating point numbers, which are
+    // normalised every day to keep them from overflowing. The initial population may not fit a
+    // f64, so it is normalised in log space.
+    fn projected_log_totals(&self, days: usize) -> Vec<f64> {
+        let initial: BigUint = self.populations.iter().map(|p| p.total(None)).sum();
+        let mut log_scale = if initial.bits() > 0 {
+            ln(&initial)
+        } else {
+            0.0
+        };
+        let mut populations = self
+            .populations
+            .iter()
+            .map(|population| {
+                population
+                    .0
+                    .iter()
+                    .map(|n| (ln(n) - log_scale).exp())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut log_totals = Vec::with_capacity(days + 1);
+        for day in 0..=days {
+            if day > 0 {
+                populations = self
+                    .transitions
+                    .iter()
+                    .zip(populations.iter())
+                    .map(|(transition, population)| transition.apply_f64(population))
+                    .collect();
+            }
+            let total: f64 = populations.iter().flatten().sum();
+            log_totals.push(log_scale + total.ln());
+            if total > 0.0 {
+                populations.iter_mut().flatten().for_each(|n| *n /= total);
+                log_scale += total.ln();
+            }
+        }
+        log_totals
+    }
+
+    fn step(&mut self) {
+        let births = self
+            .species
+            .iter()
+            .zip(self.populations.iter())
+            .map(|(species, population)| species.births(population))
+            .collect::<Vec<_>>();
+        self.populations = self
+            .transitions
+            .iter()
+            .zip(self.populations.iter())
+            .map(|(transition, population)| transition.apply(population, None))
+            .collect();
+
+        // Only newborns are turned away when the population exceeds the carrying capacity, they
+        // are split between the species in proportion to their births
+        let capacity = match &self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        let total: BigUint = self.populations.iter().map(|p| p.total(None)).sum();
+        if total <= *capacity {
+            return;
+        }
+        let all_births: BigUint = births.iter().sum();
+        let excess = (total - capacity).min(all_births.clone());
+        let mut culled = births
+            .iter()
+            .map(|births| births * &excess / &all_births)
+            .collect::<Vec<_>>();
+        let mut remaining = &excess - culled.iter().sum::<BigUint>();
+        for (births, culled) in births.iter().zip(culled.iter_mut()) {
+            let extra = (births - &*culled).min(remaining.clone());
+            *culled += &extra;
+            remaining -= extra;
+        }
+        for ((species, population), culled) in self
+            .species
+            .iter()
+            .zip(self.populations.iter_mut())
+            .zip(culled)
+        {
+            population.0[species.newborn()] -= culled;
+        }
+    }
+
+    fn run(&mut self, days: u32) -> Series {
+        let mut series = Series {
+            names: self.species.iter().map(|s| s.name.clone()).collect(),
+            timers: self.species.iter().map(Species::timers).collect(),
+            days: vec![self.populations.clone()],
+        };
+        for _ in 0..days {
+            self.step();
+            series.days.push(self.populations.clone());
+        }
+        series
+    }
+}
+
+impl Series {
+    fn totals(&self, day: usize) -> Vec<BigUint> {
+        self.days[day].iter().map(|p| p.total(None)).collect()
+    }
+
+    // The number of fish per timer value, regardless of how many times they've spawned
+    fn timer_counts(&self, day: usize, species: usize) -> Vec<BigUint> {
+        let timers = self.timers[species];
+        (0..timers)
+            .map(|timer| {
+                self.days[day][species]
+                    .0
+                    .iter()
+                    .skip(timer)
+                    .step_by(timers)
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn log_totals(&self) -> Vec<f64> {
+        (0..self.days.len())
+            .map(|day| ln(&self.totals(day).into_iter().sum()))
+            .collect()
+    }
+
+    // Totals per species and overall, followed by the timer distribution of every species
+    fn to_csv(&self) -> String {
+        let timer_columns = self
+            .names
+            .iter()
+            .zip(self.timers.iter())
+            .flat_map(|(name, timers)| (0..*timers).map(move |timer| format!("{}:{}", name, timer)))
+            .collect::<Vec<_>>();
+        let mut csv = format!(
+            "day,{},total,{}\n",
+            self.names.join(","),
+            timer_columns.join(",")
+        );
+        for day in 0..self.days.len() {
+            let totals = self.totals(day);
+            let total: BigUint = totals.iter().sum();
+            let totals = totals.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            let timer_counts = (0..self.names.len())
+                .flat_map(|species| self.timer_counts(day, species))
+                .map(|count| count.to_string())
+                .collect::<Vec<_>>();
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                day,
+                totals.join(","),
+                total,
+                timer_counts.join(",")
+            ));
+        }
+        csv
+    }
+
+    fn to_json(&self) -> Result<String> {
+        let records = (0..self.days.len())
+            .map(|day| {
+                let totals = self.totals(day);
+                DayRecord {
+                    day,
+                    total: to_number(&totals.iter().sum()),
+                    species: self
+                        .names
+                        .iter()
+                        .zip(totals.iter())
+                        .enumerate()
+                        .map(|(species, (name, total))| SpeciesRecord {
+                            name: name.clone(),
+                            total: to_number(total),
+                            timers: self
+                                .timer_counts(day, species)
+                                .iter()
+                                .map(to_number)
+                                .collect(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::to_string_pretty(&records)?)
+    }
+}
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 360.0;
+// Room on the left for the decade labels and on the right for the species names
+const CHART_PADDING: (f64, f64, f64) = (56.0, 96.0, 24.0);
+const CHART_COLORS: [&str; 5] = ["#e6550d", "#3182bd", "#31a354", "#756bb1", "#636363"];
+
+// Populations grow exponentially so every species is drawn on a log10 axis with a gridline per
+// decade, and named at the end of its curve rather than in a legend
+fn render_growth_chart(series: &Series) -> String {
+    let mut curves = (0..series.names.len())
+        .map(|species| {
+            let values = (0..series.days.len())
+                .map(|day| log10(&series.totals(day)[species]))
+                .collect::<Vec<_>>();
+            (series.names[species].as_str(), values)
+        })
+        .collect::<Vec<_>>();
+    if curves.len() > 1 {
+        let totals = series.log_totals().iter().map(|t| t / LN_10).collect();
+        curves.push(("total", totals));
+    }
+    let (left, right, vertical) = CHART_PADDING;
+    let decades = curves
+        .iter()
+        .flat_map(|(_, values)| values.iter())
+        .fold(1.0, |max: f64, value| max.max(value.ceil()));
+    let day_width =
+        (CHART_WIDTH - left - right) / series.days.len().saturating_sub(1).max(1) as f64;
+    let decade_height = (CHART_HEIGHT - 2.0 * vertical) / decades;
+    let x = |day: usize| left + day as f64 * day_width;
+    let y = |decade: f64| CHART_HEIGHT - vertical - decade.max(0.0) * decade_height;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" font-family=\"monospace\" font-size=\"11\">\n",
+        CHART_WIDTH, CHART_HEIGHT
+    );
+    // A gridline per decade, thinned out so that no more than ten are labelled
+    let every = (decades as usize).div_ceil(10);
+    for decade in (0..=decades as usize).step_by(every) {
+        svg.push_str(&format!(
+            "  <line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"#ddd\"/>\n",
+            left,
+            y(decade as f64),
+            CHART_WIDTH - right,
+            y(decade as f64)
+        ));
+        svg.push_str(&format!(
+            "  <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">1e{}</text>\n",
+            left - 4.0,
+            y(decade as f64) + 4.0,
+            decade
+        ));
+    }
+    for (index, (name, values)) in curves.iter().enumerate() {
+        let color = CHART_COLORS[index % CHART_COLORS.len()];
+        let path = values
+            .iter()
+            .enumerate()
+            .map(|(day, value)| format!("{:.1} {:.1}", x(day), y(*value)))
+            .collect::<Vec<_>>()
+            .join(" L ");
+        svg.push_str(&format!(
+            "  <path d=\"M {}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+            path, color
+        ));
+        let last = values.last().copied().unwrap_or(0.0);
+        svg.push_str(&format!(
+            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\">{}</text>\n",
+            x(values.len().saturating_sub(1)) + 4.0,
+            y(last) + 4.0,
+            color,
+            name
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn log10(value: &BigUint) -> f64 {
+    ln(value) / LN_10
+}
+
+impl Population {
+    fn total(&self, modulus: Option<&BigUint>) -> BigUint {
+        reduce(self.0.iter().sum(), modulus)
+    }
+}
+
+impl Transition {
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn apply(&self, population: &Population, modulus: Option<&BigUint>) -> Population {
+        let mut next = vec![BigUint::default(); self.size()];
+        for (fish, targets) in population.0.iter().zip(self.0.iter()) {
+            for target in targets {
+                next[*target] += fish;
+            }
+        }
+        Population(next.into_iter().map(|n| reduce(n, modulus)).collect())
+    }
+
+    fn apply_f64(&self, population: &[f64]) -> Vec<f64> {
+        let mut next = vec![0.0; self.size()];
+        for (fish, targets) in population.iter().zip(self.0.iter()) {
+            for target in targets {
+                next[*target] += fish;
+            }
+        }
+        next
+    }
+
+    fn to_matrix(&self) -> Matrix {
+        let mut matrix = Matrix::zero(self.size()).0;
+        for (from, targets) in self.0.iter().enumerate() {
+            for target in targets {
+                matrix[*target][from] += 1u32;
+            }
+        }
+        Matrix(matrix)
+    }
+
+    // Stepping costs a few additions per bucket and day while every matrix multiplication costs
+    // size³, so the matrix power only pays off for many days and few buckets
+    fn advance(&self, population: &Population, days: u64, modulus: Option<&BigUint>) -> Population {
+        let size = self.size() as u64;
+        let squarings = 64 - days.leading_zeros() as u64;
+        if size.saturating_mul(size).saturating_mul(squarings) < days {
+            return self
+                .to_matrix()
+                .pow(days, modulus)
+                .apply(population, modulus);
+        }
+        let mut population = Population(
+            population
+                .0
+                .iter()
+                .map(|n| reduce(n.clone(), modulus))
+                .collect(),
+        );
+        for _ in 0..days {
+            population = self.apply(&population, modulus);
+        }
+        population
+    }
+}
+
+impl Matrix {
+    fn zero(size: usize) -> Matrix {
+        Matrix(vec![vec![BigUint::default(); size]; size])
+    }
+
+    fn identity(size: usize) -> Matrix {
+        let mut matrix = Matrix::zero(size).0;
+        for (index, row) in matrix.iter_mut().enumerate() {
+            row[index] = BigUint::from(1u32);
+        }
+        Matrix(matrix)
+    }
+
+    fn mul(&self, other: &Matrix, modulus: Option<&BigUint>) -> Matrix {
+        let size = self.0.len();
+        Matrix(
+            (0..size)
+                .map(|row| {
+                    (0..size)
+                        .map(|column| {
+                            let sum = (0..size)
+                                .map(|k| &self.0[row][k] * &other.0[k][column])
+                                .sum();
+                            reduce(sum, modulus)
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    // Exponentiation by squaring
+    fn pow(&self, mut exponent: u64, modulus: Option<&BigUint>) -> Matrix {
+        let mut result = Matrix::identity(self.0.len());
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base, modulus);
+            }
+            base = base.mul(&base, modulus);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    fn apply(&self, population: &Population, modulus: Option<&BigUint>) -> Population {
+        Population(
+            self.0
+                .iter()
+                .map(|row| {
+                    let sum = row
+                        .iter()
+                        .zip(population.0.iter())
+                        .map(|(a, b)| a * b)
+                        .sum();
+                    reduce(sum, modulus)
+                })
+                .collect(),
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{count_fish, count_fishes};
+    use crate::{
+        count_fish, count_fishes, fit_growth_rate, render_growth_chart, Config, Matrix, Opts,
+        Simulation, Species,
+    };
+    use clap::Parser;
+    use num_bigint::BigUint;
     use yare::parameterized;
 
+    fn lanternfish(starts: &[u32]) -> Simulation {
+        with_species(Species::default(), starts)
+    }
+
+    fn with_species(species: Species, starts: &[u32]) -> Simulation {
+        let config = Config {
+            capacity: None,
+            species: vec![species],
+        };
+        Simulation::new(config, starts).unwrap()
+    }
+
     #[parameterized{
         short1 = { 0, 8, 3},
         short2 = { 7, 8, 2},
@@ -70,17 +825,370 @@ mod test {
         medium3 = { 2, 18, 5},
         medium4 = { 1, 18, 7},
     }]
-    fn test_count_fish(start: u32, days: u32, expected: u32) {
-        assert_eq!(expected, count_fish(start, days));
+    fn test_count_fish(start: u32, days: u32, expected: u64) {
+        assert_eq!(expected, count_fish(start, days, 7, 9));
+    }
+
+    #[parameterized{
+        days18 = { 18, 26 },
+        days80 = { 80, 5934 },
+    }]
+    fn test_count_fishes(days: u32, expected: u64) {
+        let starts = [3, 4, 3, 1, 2];
+
+        assert_eq!(expected, count_fishes(&starts, days, 7, 9));
     }
 
     #[parameterized{
+        days0 = { 0, 5 },
         days18 = { 18, 26 },
         days80 = { 80, 5934 },
+        days256 = { 256, 26984457539 },
+    }]
+    fn test_count_matrix(days: u32, expected: u64) {
+        let simulation = lanternfish(&[3, 4, 3, 1, 2]);
+
+        assert_eq!(BigUint::from(expected), simulation.count(days, None));
+    }
+
+    #[parameterized{
+        lanternfish = { 7, 9 },
+        short_cycle = { 3, 5 },
+        early_spawn = { 6, 2 },
+        every_day = { 1, 3 },
+    }]
+    fn test_count_matrix_naive(cycle: u32, first_spawn: u32) {
+        let species = Species {
+            cycle,
+            first_spawn,
+            ..Species::default()
+        };
+        let starts = [0, 1, 2, 0, 1];
+        let simulation = with_species(species, &starts);
+        for days in 0..60 {
+            assert_eq!(
+                BigUint::from(count_fishes(&starts, days, cycle, first_spawn)),
+                simulation.count(days, None)
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_matrix_modulus() {
+        let simulation = lanternfish(&[3, 4, 3, 1, 2]);
+        let modulus = BigUint::from(1_000_000_007u32);
+        for days in [0, 80, 256, 1000] {
+            assert_eq!(
+                simulation.count(days, None) % &modulus,
+                simulation.count(days, Some(&modulus))
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_matrix_huge() {
+        // Far beyond u64, the population grows roughly 1.09 times per day
+        let simulation = lanternfish(&[3]);
+        let fish = simulation.count(10_000, None);
+        assert!(fish.bits() > 1200);
+        let modulus = BigUint::from(998_244_353u32);
+        assert_eq!(&fish % &modulus, simulation.count(10_000, Some(&modulus)));
+    }
+
+    #[test]
+    fn test_invalid_timer() {
+        assert!(Simulation::new(
+            Config {
+                capacity: None,
+                species: vec![Species::default()]
+            },
+            &[9]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_transition_pow() {
+        let transition = Species::default().transition().to_matrix();
+        let mut expected = Matrix::identity(9);
+        for _ in 0..13 {
+            expected = expected.mul(&transition, None);
+        }
+        assert_eq!(expected, transition.pow(13, None));
+    }
+
+    #[test]
+    fn test_advance_matches_stepping() {
+        let species = Species {
+            lifespan: Some(2),
+            ..Species::default()
+        };
+        let transition = species.transition();
+        let population = species.population(&[3, 4, 3, 1, 2]).unwrap();
+        let modulus = BigUint::from(1_000_000_007u32);
+        // 18 buckets make the matrix power cheaper than stepping for this many days
+        let mut expected = population.clone();
+        for _ in 0..10_000 {
+            expected = transition.apply(&expected, Some(&modulus));
+        }
+        assert_eq!(
+            expected,
+            transition.advance(&population, 10_000, Some(&modulus))
+        );
+    }
+
+    #[test]
+    fn test_long_lifespan() {
+        // 120 spawns of 9 timers each are far too many buckets for a dense matrix power
+        let species = Species {
+            lifespan: Some(120),
+            ..Species::default()
+        };
+        let mut simulation = with_species(species, &[3, 4, 3, 1, 2]);
+        let count = simulation.count(1000, None);
+        let series = simulation.run(1000);
+        assert_eq!(series.totals(1000)[0], count);
+        assert!(count > BigUint::from(1u32) << 100usize);
+    }
+
+    // Simulates every fish as (timer, times spawned)
+    fn reference_mortality(species: &Species, starts: &[u32], days: u32) -> Vec<u64> {
+        let mut fish = starts.iter().map(|s| (*s, 0)).collect::<Vec<_>>();
+        let mut totals = vec![fish.len() as u64];
+        for _ in 0..days {
+            let mut next = Vec::new();
+            for (timer, spawned) in fish {
+                if timer > 0 {
+                    next.push((timer - 1, spawned));
+                    continue;
+                }
+                next.push((species.first_spawn - 1, 0));
+                if species.lifespan != Some(spawned + 1) {
+                    next.push((species.cycle - 1, spawned + 1));
+                }
+            }
+            fish = next;
+            totals.push(fish.len() as u64);
+        }
+        totals
+    }
+
+    #[parameterized{
+        once = { 1 },
+        twice = { 2 },
+        thrice = { 3 },
     }]
-    fn test_count_fishes(days: u32, expected: u32) {
+    fn test_mortality(lifespan: u32) {
+        let species = Species {
+            lifespan: Some(lifespan),
+            ..Species::default()
+        };
         let starts = [3, 4, 3, 1, 2];
+        let expected = reference_mortality(&species, &starts, 60);
+
+        let series = with_species(species.clone(), &starts).run(60);
+        let totals = (0..=60)
+            .map(|day| series.totals(day)[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            expected.into_iter().map(BigUint::from).collect::<Vec<_>>(),
+            totals
+        );
+        assert_eq!(totals[60], with_species(species, &starts).count(60, None));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut simulation = lanternfish(&[3, 4, 3, 1, 2]);
+        simulation.capacity = Some(BigUint::from(1000u32));
+        let series = simulation.run(200);
+
+        for day in 0..=200 {
+            assert!(series.totals(day)[0] <= BigUint::from(1000u32));
+        }
+        assert_eq!(BigUint::from(1000u32), series.totals(200)[0]);
+        // The capacity isn't reached after 18 days
+        assert_eq!(BigUint::from(26u32), series.totals(18)[0]);
+    }
+
+    #[test]
+    fn test_capacity_shared() {
+        let config = Config::parse(
+            r#"{
+                "capacity": 1001,
+                "species": [
+                    { "name": "lanternfish" },
+                    { "name": "twin", "initial": [3, 4, 3, 1, 2] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut simulation = Simulation::new(config, &[3, 4, 3, 1, 2]).unwrap();
+        let series = simulation.run(200);
+
+        let totals = series.totals(200);
+        assert_eq!(BigUint::from(1001u32), totals.iter().sum::<BigUint>());
+        assert!(totals[0] == &totals[1] + 1u32 || totals[1] == &totals[0] + 1u32);
+    }
 
-        assert_eq!(expected, count_fishes(&starts, days));
+    #[test]
+    fn test_config() {
+        let config = Config::parse(
+            r#"{
+                "species": [
+                    { "name": "slow", "cycle": 10, "first_spawn": 12, "lifespan": 4 },
+                    { "initial": [1, 2] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Config {
+                capacity: None,
+                species: vec![
+                    Species {
+                        name: "slow".to_string(),
+                        cycle: 10,
+                        first_spawn: 12,
+                        lifespan: Some(4),
+                        initial: None,
+                    },
+                    Species {
+                        initial: Some(vec![1, 2]),
+                        ..Species::default()
+                    },
+                ],
+            },
+            config
+        );
+        assert!(Config::parse(r#"{ "species": [] }"#).is_err());
+        // Misspelled keys would otherwise leave the default in place
+        assert!(Config::parse(r#"{ "species": [{ "first_spawns": 3 }] }"#).is_err());
+        assert!(Config::parse(r#"{ "capacty": 10, "species": [{}] }"#).is_err());
+        let invalid = Config::parse(r#"{ "species": [{ "cycle": 0 }] }"#).unwrap();
+        assert!(Simulation::new(invalid, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_series_csv() {
+        let config = Config::parse(
+            r#"{ "species": [{ "name": "a" }, { "name": "b", "cycle": 2, "first_spawn": 2 }] }"#,
+        )
+        .unwrap();
+        let series = Simulation::new(config, &[1]).unwrap().run(3);
+
+        assert_eq!(
+            "day,a,b,total,a:0,a:1,a:2,a:3,a:4,a:5,a:6,a:7,a:8,b:0,b:1\n\
+             0,1,1,2,0,1,0,0,0,0,0,0,0,0,1\n\
+             1,1,1,2,1,0,0,0,0,0,0,0,0,1,0\n\
+             2,2,2,4,0,0,0,0,0,0,1,0,1,0,2\n\
+             3,2,2,4,0,0,0,0,0,1,0,1,0,2,0\n",
+            series.to_csv()
+        );
+    }
+
+    #[test]
+    fn test_series_json() {
+        let config = Config::parse(r#"{ "species": [{ "name": "a", "lifespan": 2 }] }"#).unwrap();
+        let series = Simulation::new(config, &[0, 0]).unwrap().run(1);
+        let json: serde_json::Value = serde_json::from_str(&series.to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            serde_json::json!([
+                {
+                    "day": 0,
+                    "total": 2,
+                    "species": [{ "name": "a", "total": 2, "timers": [2, 0, 0, 0, 0, 0, 0, 0, 0] }]
+                },
+                {
+                    "day": 1,
+                    "total": 4,
+                    "species": [{ "name": "a", "total": 4, "timers": [0, 0, 0, 0, 0, 0, 2, 0, 2] }]
+                },
+            ]),
+            json
+        );
+    }
+
+    #[test]
+    fn test_series_json_huge() {
+        let series = lanternfish(&[3]).run(1000);
+        let json: serde_json::Value = serde_json::from_str(&series.to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            series.totals(1000)[0].to_string(),
+            json[1000]["total"].to_string()
+        );
+    }
+
+    #[test]
+    fn test_growth_chart() {
+        let config = Config::parse(r#"{ "species": [{ "name": "a" }, { "name": "b" }] }"#).unwrap();
+        let series = Simulation::new(config, &[3, 4]).unwrap().run(100);
+        let svg = render_growth_chart(&series);
+
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(3, svg.matches("<path").count());
+        assert!(svg.contains(">total</text>"));
+        // The 24896 fish after 100 days need gridlines up to 1e5
+        assert_eq!(6, svg.matches("<line").count());
+        assert!(svg.contains(">1e5</text>"));
+    }
+
+    #[test]
+    fn test_fit_growth_rate() {
+        let log_totals = (0..10)
+            .map(|day| 2.0 + day as f64 * 1.5f64.ln())
+            .collect::<Vec<_>>();
+        assert!((fit_growth_rate(&log_totals).unwrap() - 1.5).abs() < 1e-9);
+        assert_eq!(None, fit_growth_rate(&[1.0]));
+        assert_eq!(
+            Some(0.0),
+            fit_growth_rate(&[1.0, 0.5, 0.0, f64::NEG_INFINITY])
+        );
+    }
+
+    #[parameterized{
+        // The real root of x^9 = x^2 + 1
+        lanternfish = { 7, 9, None, 1.091024 },
+        lifespan = { 7, 9, Some(1), 1.0 },
+        periodic = { 2, 2, None, std::f64::consts::SQRT_2 },
+    }]
+    fn test_growth_rate(cycle: u32, first_spawn: u32, lifespan: Option<u32>, expected: f64) {
+        let species = Species {
+            cycle,
+            first_spawn,
+            lifespan,
+            ..Species::default()
+        };
+        let simulation = with_species(species, &[0, 1]);
+        let projected = fit_growth_rate(&simulation.projected_log_totals(1000)).unwrap();
+        assert!((projected - expected).abs() < 1e-3, "{}", projected);
+
+        let mut simulation = simulation;
+        let fitted = fit_growth_rate(&simulation.run(400).log_totals()).unwrap();
+        assert!((fitted - expected).abs() < 1e-3, "{}", fitted);
+    }
+
+    #[parameterized{
+        cycle = { &["--cycle", "6", "-c", "config.json"] },
+        first_spawn = { &["-c", "config.json", "--first-spawn", "3"] },
+        lifespan = { &["--lifespan", "2", "-c", "config.json"] },
+    }]
+    fn test_species_options_conflict_with_config(args: &[&str]) {
+        let args = std::iter::once("day06").chain(args.iter().cloned());
+        assert!(Opts::try_parse_from(args).is_err());
+    }
+
+    #[parameterized{
+        modulus = { &["-n", "-m", "7"] },
+        series = { &["-n", "-s", "series.csv"] },
+        chart = { &["-n", "--chart", "chart.svg"] },
+        growth = { &["-n", "-g"] },
+    }]
+    fn test_naive_conflicts(args: &[&str]) {
+        let args = std::iter::once("day06").chain(args.iter().cloned());
+        assert!(Opts::try_parse_from(args).is_err());
     }
 }
@@ -1,7 +1,7 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -11,12 +11,119 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     expensive: bool,
+    #[clap(short, long, conflicts_with = "expensive")]
+    cost: Option<String>,
+    #[clap(short, long, arg_enum, default_value = "analytic")]
+    strategy: Strategy,
+    #[clap(long, conflicts_with_all = &["all", "curve", "plot"])]
+    crabs: bool,
+    #[clap(short, long, arg_enum, default_value = "manhattan")]
+    metric: Metric,
+    #[clap(short, long)]
+    all: bool,
+    #[clap(long)]
+    curve: Option<String>,
+    #[clap(short, long)]
+    plot: bool,
+    #[clap(long, default_value = "20")]
+    plot_rows: usize,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum Strategy {
+    Analytic,
+    Ternary,
+    BruteForce,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum Metric {
+    Manhattan,
+    Euclidean,
+    Chebyshev,
+}
+
+// The fuel cost of every target from the leftmost to the rightmost crab
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CostCurve {
+    start: u32,
+    fuel: Vec<u128>,
+}
+
+// How much more fuel it costs to move one step below or above an optimal target
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Marginal {
+    target: u32,
+    below: Option<u128>,
+    above: Option<u128>,
+}
+
+// A crab at a point in one to three dimensions, the weight multiplies its fuel cost
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Crab {
+    position: Vec<u32>,
+    weight: u32,
+}
+
+enum Cost {
+    Cheap,
+    Expensive,
+    Expression(Expression),
+}
+
+// A cost expression of the distance `d`, e.g. `min(d, 10) * 3`
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Node {
+    Distance,
+    Constant(u128),
+    Add(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Mul(Box<Node>, Box<Node>),
+    Div(Box<Node>, Box<Node>),
+    Rem(Box<Node>, Box<Node>),
+    Min(Vec<Node>),
+    Max(Vec<Node>),
+    Pow(Box<Node>, Box<Node>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Number(u128),
+    Identifier(String),
+    Symbol(char),
+}
+
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
 }
 
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
     File::open(opts.input)?.read_to_string(&mut input)?;
+    let cost = match opts.cost {
+        Some(source) => Cost::Expression(Expression::parse(&source)?),
+        None if opts.expensive => Cost::Expensive,
+        None => Cost::Cheap,
+    };
+    if opts.crabs {
+        let crabs = parse_crabs(&input)?;
+        let (target, score) = optimize_crabs(&crabs, opts.metric, &cost, opts.strategy)?;
+        let target = target.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        println!(
+            "Best target is {} with a fuel cost of {}",
+            target.join(","),
+            score
+        );
+        return Ok(());
+    }
     let numbers = input
         .split(',')
         .filter_map(|n| match n.trim() {
@@ -24,51 +131,766 @@ fn main() -> Result<()> {
             n => Some(u32::from_str(n)),
         })
         .collect::<std::result::Result<Vec<_>, _>>()?;
-    let (target, score) = optimize(&numbers, opts.expensive);
+    let (target, score) = optimize_with(&numbers, &cost, opts.strategy)?;
     println!("Best target is {} with a fuel cost of {}", target, score);
+
+    if opts.all {
+        for marginal in optimal_marginals(&numbers, &cost, target, score)? {
+            let format = |cost: Option<u128>| {
+                cost.map(|c| format!("+{}", c))
+                    .unwrap_or_else(|| "-".to_string())
+            };
+            println!(
+                "Target {} is optimal, one step below costs {} and one step above costs {}",
+                marginal.target,
+                format(marginal.below),
+                format(marginal.above)
+            );
+        }
+    }
+    if opts.plot || opts.curve.is_some() {
+        let curve = cost_curve(&numbers, &cost)?;
+        if let Some(path) = opts.curve {
+            File::create(path)?.write_all(curve.to_csv().as_bytes())?;
+        }
+        if opts.plot {
+            print!("{}", curve.plot(opts.plot_rows, PLOT_WIDTH));
+        }
+    }
     Ok(())
 }
 
-fn optimize(numbers: &[u32], expensive: bool) -> (u32, u32) {
+const PLOT_WIDTH: usize = 60;
+
+// A convex curve is flat only around the optimum, so the optimal targets are found by walking out
+// from the one the optimizer picked. Other costs can be optimal anywhere and need the whole curve.
+fn optimal_marginals(
+    numbers: &[u32],
+    cost: &Cost,
+    target: u32,
+    score: u128,
+) -> Result<Vec<Marginal>> {
     let min = numbers.iter().min().cloned().unwrap();
     let max = numbers.iter().max().cloned().unwrap();
-    (min..=max)
-        .map(|t| {
-            (
-                t,
-                calc_fuel(
-                    numbers,
-                    t,
-                    if expensive {
-                        expensive_cost_function
-                    } else {
-                        cheap_cost_function
-                    },
-                ),
-            )
+    if !cost.is_convex(max - min)? {
+        return Ok(cost_curve(numbers, cost)?.marginal_costs());
+    }
+    let fuel = |t: u32| calc_fuel(numbers, t, cost);
+    let mut low = target;
+    while low > min && fuel(low - 1)? == score {
+        low -= 1;
+    }
+    let mut high = target;
+    while high < max && fuel(high + 1)? == score {
+        high += 1;
+    }
+    let below = if low > min {
+        Some(fuel(low - 1)? - score)
+    } else {
+        None
+    };
+    let above = if high < max {
+        Some(fuel(high + 1)? - score)
+    } else {
+        None
+    };
+    Ok((low..=high)
+        .map(|t| Marginal {
+            target: t,
+            below: if t == low { below } else { Some(0) },
+            above: if t == high { above } else { Some(0) },
         })
-        .min_by_key(|(_, score)| *score)
-        .unwrap()
+        .collect())
+}
+
+fn cost_curve(numbers: &[u32], cost: &Cost) -> Result<CostCurve> {
+    let min = numbers.iter().min().cloned().unwrap();
+    let max = numbers.iter().max().cloned().unwrap();
+    Ok(CostCurve {
+        start: min,
+        fuel: (min..=max)
+            .map(|t| calc_fuel(numbers, t, cost))
+            .collect::<Result<Vec<_>>>()?,
+    })
+}
+
+impl CostCurve {
+    fn minimum(&self) -> u128 {
+        self.fuel.iter().copied().min().unwrap_or(0)
+    }
+
+    fn optimal_targets(&self) -> Vec<u32> {
+        let minimum = self.minimum();
+        self.targets()
+            .filter(|(_, fuel)| *fuel == minimum)
+            .map(|(target, _)| target)
+            .collect()
+    }
+
+    fn targets(&self) -> impl Iterator<Item = (u32, u128)> + '_ {
+        (self.start..).zip(self.fuel.iter().copied())
+    }
+
+    // Steps off the curve have no marginal cost since they're beyond every crab
+    fn marginal_costs(&self) -> Vec<Marginal> {
+        let minimum = self.minimum();
+        self.optimal_targets()
+            .into_iter()
+            .map(|target| {
+                let index = (target - self.start) as usize;
+                Marginal {
+                    target,
+                    below: index.checked_sub(1).map(|below| self.fuel[below] - minimum),
+                    above: self.fuel.get(index + 1).map(|above| above - minimum),
+                }
+            })
+            .collect()
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("target,fuel\n");
+        for (target, fuel) in self.targets() {
+            csv.push_str(&format!("{},{}\n", target, fuel));
+        }
+        csv
+    }
+
+    // Horizontal bars of the cheapest target in each row, optimal rows are marked with a *
+    fn plot(&self, rows: usize, width: usize) -> String {
+        let minimum = self.minimum();
+        let chunk = self.fuel.len().div_ceil(rows.max(1)).max(1);
+        // Every row shows the cheapest target of its chunk, the first one on ties
+        let rows = self
+            .fuel
+            .chunks(chunk)
+            .enumerate()
+            .map(|(row, fuel)| {
+                let (offset, fuel) = fuel
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .min_by_key(|(offset, fuel)| (*fuel, *offset))
+                    .unwrap();
+                (self.start as usize + row * chunk + offset, fuel)
+            })
+            .collect::<Vec<_>>();
+        let maximum = rows.iter().map(|(_, fuel)| *fuel).max().unwrap_or(0);
+        let span = (maximum - minimum).max(1) as f64;
+        let label_width = (self.start as usize + self.fuel.len()).to_string().len();
+        let mut plot = String::new();
+        for (target, fuel) in rows {
+            let bar = ((fuel - minimum) as f64 / span * width as f64).round() as usize;
+            plot.push_str(&format!(
+                "{:>w$} {}|{} {}\n",
+                target,
+                if fuel == minimum { '*' } else { ' ' },
+                "#".repeat(bar),
+                fuel,
+                w = label_width
+            ));
+        }
+        plot
+    }
+}
+
+// Cost expressions have no analytic optimum, they are searched with ternary search when that's
+// safe and exhaustively otherwise
+fn optimize_with(numbers: &[u32], cost: &Cost, strategy: Strategy) -> Result<(u32, u128)> {
+    match (strategy, cost) {
+        (Strategy::BruteForce, _) => optimize(numbers, cost),
+        (Strategy::Analytic, Cost::Cheap) => optimize_analytic(numbers, false),
+        (Strategy::Analytic, Cost::Expensive) => optimize_analytic(numbers, true),
+        _ => {
+            let min = numbers.iter().min().cloned().unwrap();
+            let max = numbers.iter().max().cloned().unwrap();
+            if cost.is_convex(max - min)? {
+                optimize_ternary(numbers, cost)
+            } else {
+                optimize(numbers, cost)
+            }
+        }
+    }
+}
+
+fn optimize(numbers: &[u32], cost: &Cost) -> Result<(u32, u128)> {
+    let min = numbers.iter().min().cloned().unwrap();
+    let max = numbers.iter().max().cloned().unwrap();
+    optimize_range(numbers, min, max, cost)
+}
+
+fn optimize_range(numbers: &[u32], min: u32, max: u32, cost: &Cost) -> Result<(u32, u128)> {
+    minimize(min, max, |t| calc_fuel(numbers, t, cost))
+}
+
+// Ties go to the lowest target
+fn minimize(min: u32, max: u32, f: impl Fn(u32) -> Result<u128>) -> Result<(u32, u128)> {
+    let mut best = (min, f(min)?);
+    for t in min + 1..=max {
+        let score = f(t)?;
+        if score < best.1 {
+            best = (t, score);
+        }
+    }
+    Ok(best)
+}
+
+// The median minimises the sum of distances and the optimum for the sum of triangular numbers is
+// within half a step of the mean. Ties go to the lowest target, just like the brute force.
+fn optimize_analytic(numbers: &[u32], expensive: bool) -> Result<(u32, u128)> {
+    let mut sorted = numbers.to_vec();
+    sorted.sort_unstable();
+    if !expensive {
+        let median = sorted[(sorted.len() - 1) / 2];
+        return Ok((median, calc_fuel(numbers, median, &Cost::Cheap)?));
+    }
+    let mean = (numbers.iter().map(|n| *n as u64).sum::<u64>() / numbers.len() as u64) as u32;
+    let min = sorted[0];
+    let max = sorted[sorted.len() - 1];
+    optimize_range(
+        numbers,
+        mean.saturating_sub(1).max(min),
+        mean.saturating_add(1).min(max),
+        &Cost::Expensive,
+    )
+}
+
+fn optimize_ternary(numbers: &[u32], cost: &Cost) -> Result<(u32, u128)> {
+    let min = numbers.iter().min().cloned().unwrap();
+    let max = numbers.iter().max().cloned().unwrap();
+    let target = ternary_search(min, max, |t| calc_fuel(numbers, t, cost))?;
+    Ok((target, calc_fuel(numbers, target, cost)?))
+}
+
+// Finds the lowest minimum of a convex function. Splitting on the slope rather than on two
+// thirds keeps plateaus from throwing the search off.
+fn ternary_search(mut low: u32, mut high: u32, f: impl Fn(u32) -> Result<u128>) -> Result<u32> {
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if f(middle)? <= f(middle + 1)? {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(low)
 }
 
-fn cheap_cost_function(a: u32, b: u32) -> u32 {
-    (a as i64 - b as i64).abs() as u32
+fn cheap_cost_function(a: u32, b: u32) -> u64 {
+    a.abs_diff(b) as u64
 }
 
-fn expensive_cost_function(a: u32, b: u32) -> u32 {
+fn expensive_cost_function(a: u32, b: u32) -> u64 {
     let distance = cheap_cost_function(a, b);
     distance * (distance + 1) / 2
 }
 
-fn calc_fuel(numbers: &[u32], target: u32, cost_function: fn(u32, u32) -> u32) -> u32 {
-    numbers.iter().map(|n| cost_function(*n, target)).sum()
+fn parse_crabs(input: &str) -> Result<Vec<Crab>> {
+    let crabs = input
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .map(Crab::parse)
+        .collect::<Result<Vec<_>>>()?;
+    let dimensions = crabs
+        .first()
+        .map(|crab| crab.position.len())
+        .ok_or_else(|| anyhow!("There are no crabs"))?;
+    if let Some(crab) = crabs.iter().find(|c| c.position.len() != dimensions) {
+        bail!(
+            "Crab at {:?} doesn't have {} dimensions",
+            crab.position,
+            dimensions
+        );
+    }
+    Ok(crabs)
+}
+
+// How many crab fuel costs an exhaustive search may calculate
+const MAX_EXHAUSTIVE_EVALUATIONS: u128 = 1 << 24;
+
+// The cost is applied to the distance along every axis and the metric combines those. Manhattan
+// is separable so every axis is optimised on its own. Chebyshev is still convex when the cost is, so
+// it starts from the per-axis optimum and descends from there, only non-convex costs and the brute
+// force strategy search the whole bounding box of the crabs. Rounding the Euclidean norms breaks
+// convexity in more than one dimension, so those are always searched exhaustively. Boxes that are
+// too large to search are an error rather than a hang.
+fn optimize_crabs(
+    crabs: &[Crab],
+    metric: Metric,
+    cost: &Cost,
+    strategy: Strategy,
+) -> Result<(Vec<u32>, u128)> {
+    let dimensions = crabs[0].position.len();
+    if metric == Metric::Manhattan && strategy != Strategy::BruteForce {
+        let target = (0..dimensions)
+            .map(|axis| optimize_axis(crabs, axis, cost, strategy))
+            .collect::<Result<Vec<_>>>()?;
+        let score = calc_crab_fuel(crabs, &target, metric, cost)?;
+        return Ok((target, score));
+    }
+
+    let bounds = (0..dimensions)
+        .map(|axis| {
+            let positions = crabs.iter().map(|crab| crab.position[axis]);
+            (positions.clone().min().unwrap(), positions.max().unwrap())
+        })
+        .collect::<Vec<_>>();
+    let span = bounds.iter().map(|(min, max)| max - min).max().unwrap();
+    let convex = metric != Metric::Euclidean || dimensions == 1;
+    if strategy != Strategy::BruteForce && convex && cost.is_convex(span)? {
+        let start = (0..dimensions)
+            .map(|axis| optimize_axis(crabs, axis, cost, Strategy::Ternary))
+            .collect::<Result<Vec<_>>>()?;
+        return descend(&bounds, start, |target| {
+            calc_crab_fuel(crabs, target, metric, cost)
+        });
+    }
+
+    let targets = bounds
+        .iter()
+        .map(|(min, max)| (max - min) as u128 + 1)
+        .product::<u128>();
+    if targets * crabs.len() as u128 > MAX_EXHAUSTIVE_EVALUATIONS {
+        bail!(
+            "The {} targets around {} crabs are too many to search exhaustively",
+            targets,
+            crabs.len()
+        );
+    }
+    let mut target = bounds.iter().map(|(min, _)| *min).collect::<Vec<_>>();
+    let mut best = (
+        target.clone(),
+        calc_crab_fuel(crabs, &target, metric, cost)?,
+    );
+    // Steps through the box like an odometer so that ties go to the lowest target
+    loop {
+        let axis = match (0..dimensions)
+            .rev()
+            .find(|axis| target[*axis] < bounds[*axis].1)
+        {
+            Some(axis) => axis,
+            None => return Ok(best),
+        };
+        target[axis] += 1;
+        for (later, (min, _)) in bounds.iter().enumerate().skip(axis + 1) {
+            target[later] = *min;
+        }
+        let score = calc_crab_fuel(crabs, &target, metric, cost)?;
+        if score < best.1 {
+            best = (target.clone(), score);
+        }
+    }
+}
+
+// Pattern search over the box: moves along every axis and diagonal are tried with a step that
+// halves whenever none of them helps. The fuel is convex, so once no single step improves it the
+// target is optimal, and the best move is taken with ties going to the lowest target like brute
+// force does.
+fn descend(
+    bounds: &[(u32, u32)],
+    mut target: Vec<u32>,
+    fuel: impl Fn(&[u32]) -> Result<u128>,
+) -> Result<(Vec<u32>, u128)> {
+    let directions = (0..3usize.pow(bounds.len() as u32))
+        .map(|index| {
+            (0..bounds.len())
+                .map(|axis| (index / 3usize.pow(axis as u32)) as i64 % 3 - 1)
+                .collect::<Vec<_>>()
+        })
+        .filter(|direction| direction.iter().any(|d| *d != 0))
+        .collect::<Vec<_>>();
+    let mut score = fuel(&target)?;
+    let mut step = bounds
+        .iter()
+        .map(|(min, max)| max - min)
+        .max()
+        .unwrap()
+        .next_power_of_two() as i64;
+    while step > 0 {
+        let mut best = (score, target.clone());
+        for direction in directions.iter() {
+            let next = target
+                .iter()
+                .zip(direction)
+                .zip(bounds)
+                .map(|((t, d), (min, max))| (*t as i64 + d * step).clamp(*min as i64, *max as i64))
+                .map(|t| t as u32)
+                .collect::<Vec<_>>();
+            if next == target {
+                continue;
+            }
+            let next_score = fuel(&next)?;
+            if (next_score, &next) < (best.0, &best.1) {
+                best = (next_score, next);
+            }
+        }
+        if best.1 != target {
+            score = best.0;
+            target = best.1;
+        } else {
+            step /= 2;
+        }
+    }
+    Ok((target, score))
+}
+
+// The weighted median and weighted mean play the same role as in `optimize_analytic`
+fn optimize_axis(crabs: &[Crab], axis: usize, cost: &Cost, strategy: Strategy) -> Result<u32> {
+    let mut positions = crabs
+        .iter()
+        .map(|crab| (crab.position[axis], crab.weight as u128))
+        .collect::<Vec<_>>();
+    positions.sort_unstable();
+    let fuel = |t: u32| {
+        positions.iter().try_fold(0u128, |sum, (position, weight)| {
+            cost.fuel_for(position.abs_diff(t) as u64)?
+                .checked_mul(*weight)
+                .and_then(|fuel| sum.checked_add(fuel))
+                .ok_or_else(|| anyhow!("The fuel cost for {} on axis {} overflows", t, axis))
+        })
+    };
+    let min = positions[0].0;
+    let max = positions[positions.len() - 1].0;
+    let total_weight: u128 = positions.iter().map(|(_, weight)| weight).sum();
+    match (strategy, cost) {
+        (Strategy::Analytic, Cost::Cheap) => {
+            let mut cumulative = 0;
+            for (position, weight) in positions.iter() {
+                cumulative += weight;
+                if cumulative * 2 >= total_weight {
+                    return Ok(*position);
+                }
+            }
+            Ok(max)
+        }
+        (Strategy::Analytic, Cost::Expensive) if total_weight > 0 => {
+            let weighted_sum: u128 = positions
+                .iter()
+                .map(|(position, weight)| *position as u128 * weight)
+                .sum();
+            let mean = (weighted_sum / total_weight) as u32;
+            let (target, _) = minimize(
+                mean.saturating_sub(1).max(min),
+                mean.saturating_add(1).min(max),
+                fuel,
+            )?;
+            Ok(target)
+        }
+        _ if cost.is_convex(max - min)? => ternary_search(min, max, fuel),
+        _ => Ok(minimize(min, max, fuel)?.0),
+    }
+}
+
+fn calc_crab_fuel(crabs: &[Crab], target: &[u32], metric: Metric, cost: &Cost) -> Result<u128> {
+    crabs.iter().try_fold(0u128, |sum, crab| {
+        let axis_fuel = crab
+            .position
+            .iter()
+            .zip(target)
+            .map(|(position, t)| cost.fuel_for(position.abs_diff(*t) as u64))
+            .collect::<Result<Vec<_>>>()?;
+        metric
+            .combine(&axis_fuel)
+            .and_then(|fuel| fuel.checked_mul(crab.weight as u128))
+            .and_then(|fuel| sum.checked_add(fuel))
+            .ok_or_else(|| anyhow!("The fuel cost for target {:?} overflows", target))
+    })
+}
+
+impl Crab {
+    // A crab is written as x[,y[,z]] optionally followed by its weight, e.g. `3,4 2`
+    fn parse(line: &str) -> Result<Crab> {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        let (position, weight) = match parts.as_slice() {
+            [position] => (position, 1),
+            [position, weight] => (position, u32::from_str(weight)?),
+            _ => bail!("Invalid crab {}", line),
+        };
+        let position = position
+            .split(',')
+            .map(|c| Ok(u32::from_str(c.trim())?))
+            .collect::<Result<Vec<_>>>()?;
+        if position.is_empty() || position.len() > 3 {
+            bail!("Crab {} must have one to three coordinates", line);
+        }
+        Ok(Crab { position, weight })
+    }
+}
+
+// The largest integer whose square doesn't exceed n
+fn integer_sqrt(n: u128) -> u128 {
+    let (mut low, mut high) = (0u128, 1u128 << 64);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if middle.checked_mul(middle).is_some_and(|square| square <= n) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+impl Metric {
+    // Euclidean norms are rounded to the nearest integer, overflows are None
+    fn combine(&self, values: &[u128]) -> Option<u128> {
+        match self {
+            Metric::Manhattan => values.iter().try_fold(0u128, |sum, v| sum.checked_add(*v)),
+            Metric::Chebyshev => Some(values.iter().copied().max().unwrap_or(0)),
+            Metric::Euclidean => {
+                let squared = values.iter().try_fold(0u128, |sum, v| {
+                    v.checked_mul(*v).and_then(|square| sum.checked_add(square))
+                })?;
+                let root = integer_sqrt(squared);
+                if squared - root * root > root {
+                    Some(root + 1)
+                } else {
+                    Some(root)
+                }
+            }
+        }
+    }
+}
+
+fn calc_fuel(numbers: &[u32], target: u32, cost: &Cost) -> Result<u128> {
+    numbers.iter().try_fold(0u128, |sum, n| {
+        sum.checked_add(cost.fuel(*n, target)?)
+            .ok_or_else(|| anyhow!("The fuel cost for target {} overflows", target))
+    })
+}
+
+impl Cost {
+    fn fuel(&self, a: u32, b: u32) -> Result<u128> {
+        match self {
+            Cost::Cheap => Ok(cheap_cost_function(a, b) as u128),
+            Cost::Expensive => Ok(expensive_cost_function(a, b) as u128),
+            Cost::Expression(expression) => expression.evaluate(a.abs_diff(b) as u64),
+        }
+    }
+
+    fn fuel_for(&self, distance: u64) -> Result<u128> {
+        let distance = distance as u128;
+        match self {
+            Cost::Cheap => Ok(distance),
+            Cost::Expensive => Ok(distance * (distance + 1) / 2),
+            Cost::Expression(expression) => expression.evaluate(distance as u64),
+        }
+    }
+
+    // The total fuel is convex in the target when the cost never decreases with the distance
+    // and its increments never shrink
+    fn is_convex(&self, max_distance: u32) -> Result<bool> {
+        let expression = match self {
+            Cost::Expression(expression) => expression,
+            _ => return Ok(true),
+        };
+        let mut previous = expression.evaluate(0)?;
+        let mut previous_step = 0;
+        for distance in 1..=max_distance {
+            let current = expression.evaluate(distance as u64)?;
+            let step = match current.checked_sub(previous) {
+                Some(step) if step >= previous_step => step,
+                _ => return Ok(false),
+            };
+            previous = current;
+            previous_step = step;
+        }
+        Ok(true)
+    }
+}
+
+impl Expression {
+    fn parse(source: &str) -> Result<Expression> {
+        let mut parser = ExpressionParser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let root = parser.parse_sum()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?} in cost {}", token, source);
+        }
+        Ok(Expression {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    fn evaluate(&self, distance: u64) -> Result<u128> {
+        self.root.evaluate(distance as u128).ok_or_else(|| {
+            anyhow!(
+                "The cost {} can't be evaluated for distance {}",
+                self.source,
+                distance
+            )
+        })
+    }
+}
+
+impl Node {
+    // Overflows, negative results and division by zero evaluate to None
+    fn evaluate(&self, distance: u128) -> Option<u128> {
+        match self {
+            Node::Distance => Some(distance),
+            Node::Constant(value) => Some(*value),
+            Node::Add(a, b) => a.evaluate(distance)?.checked_add(b.evaluate(distance)?),
+            Node::Sub(a, b) => a.evaluate(distance)?.checked_sub(b.evaluate(distance)?),
+            Node::Mul(a, b) => a.evaluate(distance)?.checked_mul(b.evaluate(distance)?),
+            Node::Div(a, b) => a.evaluate(distance)?.checked_div(b.evaluate(distance)?),
+            Node::Rem(a, b) => a.evaluate(distance)?.checked_rem(b.evaluate(distance)?),
+            Node::Min(arguments) => arguments
+                .iter()
+                .map(|a| a.evaluate(distance))
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min(),
+            Node::Max(arguments) => arguments
+                .iter()
+                .map(|a| a.evaluate(distance))
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .max(),
+            Node::Pow(a, b) => {
+                let exponent = u32::try_from(b.evaluate(distance)?).ok()?;
+                a.evaluate(distance)?.checked_pow(exponent)
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut number = c.to_string();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                number.push(digit);
+            }
+            tokens.push(Token::Number(u128::from_str(&number)?));
+        } else if c.is_ascii_alphabetic() {
+            let mut identifier = c.to_string();
+            while let Some(letter) = chars.next_if(char::is_ascii_alphanumeric) {
+                identifier.push(letter);
+            }
+            tokens.push(Token::Identifier(identifier));
+        } else if "+-*/%(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+        } else {
+            bail!("Unexpected character {} in cost {}", c, source);
+        }
+    }
+    Ok(tokens)
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of cost"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        match self.next()? {
+            Token::Symbol(c) if c == symbol => Ok(()),
+            token => Err(anyhow!("Expected {} but found {:?}", symbol, token)),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Node> {
+        let mut node = self.parse_product()?;
+        while let Some(Token::Symbol(symbol @ ('+' | '-'))) = self.peek().cloned() {
+            self.position += 1;
+            let right = Box::new(self.parse_product()?);
+            node = match symbol {
+                '+' => Node::Add(Box::new(node), right),
+                _ => Node::Sub(Box::new(node), right),
+            };
+        }
+        Ok(node)
+    }
+
+    fn parse_product(&mut self) -> Result<Node> {
+        let mut node = self.parse_factor()?;
+        while let Some(Token::Symbol(symbol @ ('*' | '/' | '%'))) = self.peek().cloned() {
+            self.position += 1;
+            let right = Box::new(self.parse_factor()?);
+            node = match symbol {
+                '*' => Node::Mul(Box::new(node), right),
+                '/' => Node::Div(Box::new(node), right),
+                _ => Node::Rem(Box::new(node), right),
+            };
+        }
+        Ok(node)
+    }
+
+    fn parse_factor(&mut self) -> Result<Node> {
+        match self.next()? {
+            Token::Number(value) => Ok(Node::Constant(value)),
+            Token::Symbol('(') => {
+                let node = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Token::Identifier(name) if name == "d" => Ok(Node::Distance),
+            Token::Identifier(name) => {
+                self.expect('(')?;
+                let mut arguments = vec![self.parse_sum()?];
+                while let Some(Token::Symbol(',')) = self.peek() {
+                    self.position += 1;
+                    arguments.push(self.parse_sum()?);
+                }
+                self.expect(')')?;
+                match (name.as_str(), arguments.len()) {
+                    ("min", _) => Ok(Node::Min(arguments)),
+                    ("max", _) => Ok(Node::Max(arguments)),
+                    ("pow", 2) => {
+                        let exponent = Box::new(arguments.pop().unwrap());
+                        Ok(Node::Pow(Box::new(arguments.pop().unwrap()), exponent))
+                    }
+                    _ => Err(anyhow!(
+                        "Unknown function {} with {} arguments",
+                        name,
+                        arguments.len()
+                    )),
+                }
+            }
+            token => Err(anyhow!("Unexpected {:?} in cost", token)),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{cheap_cost_function, expensive_cost_function, optimize};
+    use crate::{
+        cheap_cost_function, cost_curve, expensive_cost_function, optimal_marginals, optimize,
+        optimize_analytic, optimize_crabs, optimize_ternary, optimize_with, parse_crabs,
+        ternary_search, Cost, Crab, Expression, Marginal, Metric, Node, Opts, Strategy,
+    };
+    use clap::Parser;
     use yare::parameterized;
 
+    // The generated tests draw their inputs from a seeded linear congruential generator
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, bound: u64) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % bound) as u32
+        }
+    }
+
     #[parameterized{
         one = {16, 2, 14},
         two = {1, 2, 1},
@@ -78,7 +900,7 @@ mod test {
         six = {7, 2, 5},
         seven = {14, 2, 12},
     }]
-    fn test_cheap_cost_function(a: u32, b: u32, expected: u32) {
+    fn test_cheap_cost_function(a: u32, b: u32, expected: u64) {
         assert_eq!(expected, cheap_cost_function(a, b,));
     }
 
@@ -91,14 +913,384 @@ mod test {
     six = {7, 5, 3},
     seven = {14, 5, 45},
     }]
-    fn test_expensive_cost_function(a: u32, b: u32, expected: u32) {
+    fn test_expensive_cost_function(a: u32, b: u32, expected: u64) {
         assert_eq!(expected, expensive_cost_function(a, b,));
     }
 
     #[test]
     fn test_optimize() {
         let numbers = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
-        let result = optimize(&numbers, false);
+        let result = optimize(&numbers, &Cost::Cheap).unwrap();
         assert_eq!((2, 37), result);
     }
+
+    #[parameterized{
+        cheap = { false, (2, 37) },
+        expensive = { true, (5, 168) },
+    }]
+    fn test_optimize_fast(expensive: bool, expected: (u32, u128)) {
+        let numbers = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
+        let cost = if expensive {
+            Cost::Expensive
+        } else {
+            Cost::Cheap
+        };
+        assert_eq!(expected, optimize(&numbers, &cost).unwrap());
+        assert_eq!(expected, optimize_analytic(&numbers, expensive).unwrap());
+        assert_eq!(expected, optimize_ternary(&numbers, &cost).unwrap());
+    }
+
+    #[parameterized{
+        seed1 = { 1 },
+        seed2 = { 2021 },
+        seed3 = { 31337 },
+    }]
+    fn test_optimize_generated(seed: u64) {
+        let mut random = Lcg(seed);
+        for _ in 0..200 {
+            let count = random.below(20) as usize + 1;
+            let spread = random.below(200) as u64 + 1;
+            let offset = random.below(1000);
+            let numbers = (0..count)
+                .map(|_| offset + random.below(spread))
+                .collect::<Vec<_>>();
+            for (expensive, cost) in [(false, Cost::Cheap), (true, Cost::Expensive)] {
+                let expected = optimize(&numbers, &cost).unwrap();
+                assert_eq!(expected, optimize_analytic(&numbers, expensive).unwrap());
+                assert_eq!(expected, optimize_ternary(&numbers, &cost).unwrap());
+            }
+            for source in [
+                "d*d",
+                "d*(d+1)/2",
+                "min(d, 10)*3",
+                "pow(d, 3) + 2*d",
+                "d % 7",
+            ] {
+                let cost = Cost::Expression(Expression::parse(source).unwrap());
+                let expected = optimize(&numbers, &cost).unwrap();
+                for strategy in [Strategy::Analytic, Strategy::Ternary] {
+                    assert_eq!(expected, optimize_with(&numbers, &cost, strategy).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimize_large_range() {
+        let numbers = [0, 3_000_000, 1_000_000, 4_000_000, 2_500_000];
+        assert_eq!(
+            (2_500_000, 6_000_000),
+            optimize_analytic(&numbers, false).unwrap()
+        );
+        let (target, score) = optimize_analytic(&numbers, true).unwrap();
+        assert_eq!(2_100_000, target);
+        assert_eq!(
+            (target, score),
+            optimize_ternary(&numbers, &Cost::Expensive).unwrap()
+        );
+    }
+
+    #[parameterized{
+        valley = { 0, 10, vec![5, 4, 3, 2, 3, 4, 5, 6, 7, 8, 9], 3 },
+        plateau = { 0, 10, vec![5, 4, 3, 3, 3, 3, 5, 6, 7, 8, 9], 2 },
+        increasing = { 0, 10, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10], 0 },
+        decreasing = { 0, 10, vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0], 10 },
+        single = { 4, 4, vec![0, 0, 0, 0, 7], 4 },
+    }]
+    fn test_ternary_search(low: u32, high: u32, values: Vec<u128>, expected: u32) {
+        assert_eq!(
+            expected,
+            ternary_search(low, high, |t| Ok(values[t as usize])).unwrap()
+        );
+    }
+
+    #[parameterized{
+        distance = { "d", 7, Some(7) },
+        square = { "d*d", 7, Some(49) },
+        triangular = { "d*(d+1)/2", 7, Some(28) },
+        capped = { "min(d, 10)*3", 12, Some(30) },
+        precedence = { "1 + 2 * d - 3 % 2", 4, Some(8) },
+        max = { "max(d, 5, 2*d - 10)", 20, Some(30) },
+        power = { "pow(2, d)", 100, Some(1 << 100) },
+        underflow = { "d - 10", 5, None },
+        division_by_zero = { "10 / (d - 3)", 3, None },
+        overflow = { "pow(d, 200)", 10, None },
+    }]
+    fn test_expression(source: &str, distance: u64, expected: Option<u128>) {
+        let expression = Expression::parse(source).unwrap();
+        assert_eq!(expected, expression.evaluate(distance).ok());
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        assert_eq!(
+            Node::Mul(
+                Box::new(Node::Min(vec![Node::Distance, Node::Constant(10)])),
+                Box::new(Node::Constant(3))
+            ),
+            Expression::parse("min(d, 10)*3").unwrap().root
+        );
+        for source in [
+            "", "d +", "(d", "d)", "x", "foo(d)", "pow(d)", "d ^ 2", "d d",
+        ] {
+            assert!(Expression::parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[parameterized{
+        linear = { "d", true },
+        square = { "d*d", true },
+        triangular = { "d*(d+1)/2", true },
+        capped = { "min(d, 10)*3", false },
+        decreasing = { "100 - d", false },
+        periodic = { "d % 7", false },
+        constant = { "5", true },
+    }]
+    fn test_is_convex(source: &str, expected: bool) {
+        let cost = Cost::Expression(Expression::parse(source).unwrap());
+        assert_eq!(expected, cost.is_convex(50).unwrap());
+    }
+
+    #[test]
+    fn test_expression_overflow() {
+        // Far beyond what fits in a u64
+        let numbers = [0, 2_000_000, 4_000_000];
+        let cost = Cost::Expression(Expression::parse("d*d*d*d").unwrap());
+        assert_eq!(
+            (2_000_000, 2 * 16_000_000_000_000_000_000_000_000),
+            optimize_with(&numbers, &cost, Strategy::Analytic).unwrap()
+        );
+        let cost = Cost::Expression(Expression::parse("pow(d, 6)").unwrap());
+        assert!(optimize_with(&numbers, &cost, Strategy::Ternary).is_err());
+    }
+
+    #[parameterized{
+        one = { "7", Some((vec![7], 1)) },
+        two = { "1,2", Some((vec![1, 2], 1)) },
+        weighted = { " 1,2,3  4 ", Some((vec![1, 2, 3], 4)) },
+        four = { "1,2,3,4", None },
+        negative = { "-1,2", None },
+        extra = { "1,2 3 4", None },
+    }]
+    fn test_parse_crab(source: &str, expected: Option<(Vec<u32>, u32)>) {
+        let expected = expected.map(|(position, weight)| Crab { position, weight });
+        assert_eq!(expected, Crab::parse(source).ok());
+    }
+
+    #[test]
+    fn test_parse_crabs() {
+        assert_eq!(2, parse_crabs("1,2\n3,4 5\n\n").unwrap().len());
+        assert!(parse_crabs("1,2\n3").is_err());
+        assert!(parse_crabs("\n").is_err());
+    }
+
+    #[parameterized{
+        manhattan = { Metric::Manhattan, vec![3, 4], Some(7) },
+        chebyshev = { Metric::Chebyshev, vec![3, 4], Some(4) },
+        euclidean = { Metric::Euclidean, vec![3, 4], Some(5) },
+        euclidean_down = { Metric::Euclidean, vec![1, 2], Some(2) },
+        euclidean_up = { Metric::Euclidean, vec![2, 2], Some(3) },
+        euclidean_3d = { Metric::Euclidean, vec![2, 3, 6], Some(7) },
+        one_dimension = { Metric::Euclidean, vec![7], Some(7) },
+        huge = { Metric::Euclidean, vec![u64::MAX as u128, 0], Some(u64::MAX as u128) },
+        overflow = { Metric::Euclidean, vec![u64::MAX as u128 + 1], None },
+        manhattan_overflow = { Metric::Manhattan, vec![u128::MAX, 1], None },
+    }]
+    fn test_metric_combine(metric: Metric, values: Vec<u128>, expected: Option<u128>) {
+        assert_eq!(expected, metric.combine(&values));
+    }
+
+    #[parameterized{
+        cheap = { false, 2, 37 },
+        expensive = { true, 5, 168 },
+    }]
+    fn test_optimize_crabs_one_dimension(expensive: bool, target: u32, score: u128) {
+        let crabs = parse_crabs("16\n1\n2\n0\n4\n2\n7\n1\n2\n14").unwrap();
+        let cost = if expensive {
+            Cost::Expensive
+        } else {
+            Cost::Cheap
+        };
+        for metric in [Metric::Manhattan, Metric::Euclidean, Metric::Chebyshev] {
+            for strategy in [Strategy::Analytic, Strategy::Ternary, Strategy::BruteForce] {
+                assert_eq!(
+                    (vec![target], score),
+                    optimize_crabs(&crabs, metric, &cost, strategy).unwrap()
+                );
+            }
+        }
+    }
+
+    #[parameterized{
+        weighted = { "0 1\n10 3", Metric::Manhattan, Strategy::Analytic, (vec![10], 10) },
+        chebyshev = { "0,0\n4,2", Metric::Chebyshev, Strategy::Analytic, (vec![0, 0], 4) },
+        euclidean = { "0,0\n6,8 2", Metric::Euclidean, Strategy::Analytic, (vec![6, 8], 10) },
+        manhattan = {
+            "0,0,0 2\n2,4,6\n4,0,2", Metric::Manhattan, Strategy::Analytic, (vec![0, 0, 0], 18)
+        },
+    }]
+    fn test_optimize_crabs(
+        source: &str,
+        metric: Metric,
+        strategy: Strategy,
+        expected: (Vec<u32>, u128),
+    ) {
+        let crabs = parse_crabs(source).unwrap();
+        assert_eq!(
+            expected,
+            optimize_crabs(&crabs, metric, &Cost::Cheap, strategy).unwrap()
+        );
+        assert_eq!(
+            expected,
+            optimize_crabs(&crabs, metric, &Cost::Cheap, Strategy::BruteForce).unwrap()
+        );
+    }
+
+    #[parameterized{
+        seed1 = { 1 },
+        seed2 = { 2021 },
+    }]
+    fn test_optimize_crabs_generated(seed: u64) {
+        let mut random = Lcg(seed);
+        for _ in 0..30 {
+            let dimensions = random.below(2) as usize + 2;
+            let crabs = (0..random.below(8) + 1)
+                .map(|_| Crab {
+                    position: (0..dimensions).map(|_| random.below(12)).collect(),
+                    weight: random.below(5),
+                })
+                .collect::<Vec<_>>();
+            let costs = [
+                Cost::Cheap,
+                Cost::Expensive,
+                Cost::Expression(Expression::parse("d*d").unwrap()),
+                Cost::Expression(Expression::parse("min(d, 4)").unwrap()),
+            ];
+            for cost in costs.iter() {
+                for metric in [Metric::Manhattan, Metric::Euclidean, Metric::Chebyshev] {
+                    let expected =
+                        optimize_crabs(&crabs, metric, cost, Strategy::BruteForce).unwrap();
+                    for strategy in [Strategy::Analytic, Strategy::Ternary] {
+                        assert_eq!(
+                            expected,
+                            optimize_crabs(&crabs, metric, cost, strategy).unwrap()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimize_crabs_wide_spread() {
+        let crabs = parse_crabs("0,0,0\n1000000,1000000,1000000\n1000000,0,500000 2").unwrap();
+        assert_eq!(
+            (vec![500000, 500000, 500000], 2000000),
+            optimize_crabs(&crabs, Metric::Chebyshev, &Cost::Cheap, Strategy::Analytic).unwrap()
+        );
+        assert!(
+            optimize_crabs(&crabs, Metric::Euclidean, &Cost::Cheap, Strategy::Analytic).is_err()
+        );
+    }
+
+    #[test]
+    fn test_optimize_crabs_too_many_targets() {
+        let crabs = parse_crabs("0,0\n60000,80000 2").unwrap();
+        for metric in [Metric::Manhattan, Metric::Euclidean, Metric::Chebyshev] {
+            assert!(optimize_crabs(&crabs, metric, &Cost::Cheap, Strategy::BruteForce).is_err());
+        }
+        for strategy in [Strategy::Analytic, Strategy::Ternary] {
+            assert!(optimize_crabs(&crabs, Metric::Euclidean, &Cost::Cheap, strategy).is_err());
+            assert_eq!(
+                (vec![60000, 80000], 140000),
+                optimize_crabs(&crabs, Metric::Manhattan, &Cost::Cheap, strategy).unwrap()
+            );
+        }
+    }
+
+    fn marginal(target: u32, below: Option<u128>, above: Option<u128>) -> Marginal {
+        Marginal {
+            target,
+            below,
+            above,
+        }
+    }
+
+    #[parameterized{
+        cheap = { vec![16, 1, 2, 0, 4, 2, 7, 1, 2, 14], "d", vec![marginal(2, Some(4), Some(2))] },
+        expensive = { vec![16, 1, 2, 0, 4, 2, 7, 1, 2, 14], "d*(d+1)/2", vec![marginal(5, Some(2), Some(8))] },
+        plateau = { vec![0, 4], "d", vec![
+            marginal(0, None, Some(0)),
+            marginal(1, Some(0), Some(0)),
+            marginal(2, Some(0), Some(0)),
+            marginal(3, Some(0), Some(0)),
+            marginal(4, Some(0), None),
+        ] },
+        separate = { vec![0, 14], "d % 7", vec![
+            marginal(0, None, Some(7)),
+            marginal(7, Some(7), Some(7)),
+            marginal(14, Some(7), None),
+        ] },
+    }]
+    fn test_marginal_costs(numbers: Vec<u32>, source: &str, expected: Vec<Marginal>) {
+        let cost = Cost::Expression(Expression::parse(source).unwrap());
+        let curve = cost_curve(&numbers, &cost).unwrap();
+
+        assert_eq!(
+            expected.iter().map(|m| m.target).collect::<Vec<_>>(),
+            curve.optimal_targets()
+        );
+        assert_eq!(expected, curve.marginal_costs());
+        // The single best target is always the lowest optimal one
+        let (target, score) = optimize(&numbers, &cost).unwrap();
+        assert_eq!((expected[0].target, curve.minimum()), (target, score));
+        assert_eq!(
+            expected,
+            optimal_marginals(&numbers, &cost, target, score).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_optimal_marginals_wide_spread() {
+        let numbers = [0, 5, 4000000000];
+        let (target, score) = optimize_with(&numbers, &Cost::Cheap, Strategy::Analytic).unwrap();
+        assert_eq!(
+            vec![marginal(5, Some(1), Some(1))],
+            optimal_marginals(&numbers, &Cost::Cheap, target, score).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cost_curve_csv() {
+        let curve = cost_curve(&[1, 3], &Cost::Expensive).unwrap();
+        assert_eq!("target,fuel\n1,3\n2,2\n3,3\n", curve.to_csv());
+    }
+
+    #[test]
+    fn test_cost_curve_plot() {
+        let curve = cost_curve(&[0, 4], &Cost::Expensive).unwrap();
+        assert_eq!(
+            "0  |########## 10\n\
+             1  |### 7\n\
+             2 *| 6\n\
+             3  |### 7\n\
+             4  |########## 10\n",
+            curve.plot(10, 10)
+        );
+        let plot = curve.plot(2, 10);
+        assert_eq!(
+            vec!["2 *| 6", "3  |########## 7"],
+            plot.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[parameterized{
+        all = { &["--crabs", "-a"] },
+        curve = { &["--crabs", "--curve", "curve.csv"] },
+        plot = { &["--crabs", "-p"] },
+    }]
+    fn test_crabs_conflicts(args: &[&str]) {
+        let args = std::iter::once("day07").chain(args.iter().cloned());
+        assert!(Opts::try_parse_from(args).is_err());
+    }
 }
@@ -1,10 +1,20 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser};
+use itertools::Itertools;
 
-use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::Read;
 
+// Ambiguous signals list at most this many wirings
+const MAX_WIRINGS: usize = 24;
+
+// The segments of 0 to 9, one bit per segment in the order of Segment
+const DIGIT_GLYPHS: [u8; 10] = [
+    0b1110111, 0b0100100, 0b1011101, 0b1101101, 0b0101110, 0b1101011, 0b1111011, 0b0100101,
+    0b1111111, 0b1101111,
+];
+
 #[derive(Parser)]
 #[clap(version = "1.0", author = "Raniz")]
 struct Opts {
@@ -12,33 +22,105 @@ struct Opts {
     input: String,
     #[clap(short, long)]
     solve: bool,
+    #[clap(short, long)]
+    glyphs: Option<String>,
+    #[clap(short, long)]
+    diagnose: bool,
+    #[clap(long, arg_enum, default_value = "constraint")]
+    strategy: Strategy,
+    #[clap(short, long, conflicts_with = "glyphs")]
+    render: bool,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum Strategy {
+    Constraint,
+    BruteForce,
 }
 
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
     let mut input = String::new();
     File::open(opts.input)?.read_to_string(&mut input)?;
-    let signals = input
-        .split('\n')
-        .map(Signal::parse)
-        .collect::<Result<Vec<_>>>()?;
-    if opts.solve {
-        let sum: u64 = signals
-            .iter()
-            .map(|signal| signal.get_output() as u64)
-            .sum();
-        println!("{}", sum);
-    } else {
-        let count: u32 = signals.iter().map(Signal::known_output_digits).sum();
-        println!("{}", count);
+    // Lines that can't be parsed are reported like the ones that can't be decoded
+    let mut signals = Vec::new();
+    for (line, signal) in parse_signals(&input) {
+        match signal {
+            Ok(signal) => signals.push((line, signal)),
+            Err(e) => eprintln!("Line {}: {}", line, e),
+        }
+    }
+    let table = match opts.glyphs {
+        Some(path) => {
+            let mut glyphs = String::new();
+            File::open(path)?.read_to_string(&mut glyphs)?;
+            Some(GlyphTable::parse(&glyphs)?)
+        }
+        None => None,
+    };
+    match (opts.solve, table) {
+        _ if opts.render => {
+            for (line, signal) in &signals {
+                println!("{}: {}", line, signal.render(opts.strategy));
+            }
+        }
+        (_, table) if opts.diagnose => {
+            let table = table.unwrap_or_else(GlyphTable::seven_segment);
+            for (line, signal) in &signals {
+                match signal.decode_with(&table, opts.strategy) {
+                    Ok(decoding) => println!("{}: {}", line, decoding),
+                    Err(e) => eprintln!("Line {}: {}", line, e),
+                }
+            }
+        }
+        (true, Some(table)) => {
+            for (line, signal) in &signals {
+                match signal.decode_output(&table, opts.strategy) {
+                    Ok(output) => println!("{}", output),
+                    Err(e) => eprintln!("Line {}: {}", line, e),
+                }
+            }
+        }
+        (true, None) => {
+            let mut sum = 0u64;
+            for (line, signal) in &signals {
+                match signal.get_output(opts.strategy) {
+                    Ok(output) => {
+                        sum = sum
+                            .checked_add(output)
+                            .ok_or_else(|| anyhow!("Sum of outputs overflows at line {}", line))?
+                    }
+                    Err(e) => eprintln!("Line {}: {}", line, e),
+                }
+            }
+            println!("{}", sum);
+        }
+        (false, table) => {
+            let table = table.unwrap_or_else(GlyphTable::seven_segment);
+            let count: u32 = signals
+                .iter()
+                .map(|(_, signal)| signal.known_output_digits(&table))
+                .sum();
+            println!("{}", count);
+        }
     }
     Ok(())
 }
 
+// Numbers the lines from 1 and skips blank ones
+fn parse_signals(input: &str) -> Vec<(usize, Result<Signal<'_>>)> {
+    input
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| (index + 1, Signal::parse(line)))
+        .collect()
+}
+
 #[derive(Debug)]
 struct Signal<'a> {
-    pattern: [Digit<'a>; 10],
-    output: [Digit<'a>; 4],
+    pattern: Vec<Digit<'a>>,
+    output: Vec<Digit<'a>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -55,179 +137,572 @@ enum Segment {
     Bottom = 6,
 }
 
+// A glyph is the set of segments it lights, one bit per segment
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct GlyphTable {
+    segments: usize,
+    glyphs: Vec<(char, u32)>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Decoding {
+    Unique(Vec<char>),
+    Ambiguous(Vec<Vec<char>>),
+    Inconsistent(String),
+}
+
+// Candidate segments for every wire and candidate glyphs for every pattern
+#[derive(Clone, Debug)]
+struct Constraints {
+    domains: Vec<u32>,
+    candidates: Vec<Vec<usize>>,
+}
+
+struct Decoder<'t> {
+    table: &'t GlyphTable,
+    patterns: Vec<u32>,
+}
+
 impl<'a> Signal<'a> {
-    fn parse(line: &str) -> Result<Signal> {
+    fn parse(line: &str) -> Result<Signal<'_>> {
         match line.split(" | ").collect::<Vec<_>>().as_slice() {
             [pattern, output] => Ok(Signal {
-                pattern: pattern
-                    .split(' ')
-                    .map(|d| Digit(d))
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .map_err(|v: Vec<Digit>| {
-                        anyhow!("Expected vec of size 10 but was {}", v.len())
-                    })?,
-                output: output
-                    .split(' ')
-                    .map(|d| Digit(d))
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .map_err(|v: Vec<Digit>| {
-                        anyhow!("Expected vec of size 4 but was {}", v.len())
-                    })?,
+                pattern: pattern.split(' ').map(Digit).collect(),
+                output: output.split(' ').map(Digit).collect(),
             }),
             _ => Err(anyhow!("Invalid signal {}", line)),
         }
     }
 
-    fn known_output_digits(&self) -> u32 {
+    fn known_output_digits(&self, table: &GlyphTable) -> u32 {
+        let lengths = table.unique_lengths();
         self.output
             .iter()
-            .filter(|digit| [2, 3, 4, 7].contains(&digit.0.len()))
+            .filter(|digit| lengths.contains(&digit.0.len()))
             .count() as u32
     }
 
-    pub fn get_output(&self) -> u32 {
-        let wiring = self.deduce_wiring();
+    pub fn get_output(&self, strategy: Strategy) -> Result<u64> {
+        let wiring = self.deduce_wiring(strategy)?;
+        let table = GlyphTable::seven_segment();
         self.output
             .iter()
-            .map(|d| d.get_value(&wiring))
-            .rev()
-            .enumerate()
-            .map(|(index, value)| 10u32.pow(index as u32) * value as u32)
-            .sum()
-    }
-
-    pub(crate) fn deduce_wiring(&self) -> [char; 7] {
-        self.deduce_wiring_rec(['x'; 7])
-    }
-
-    fn deduce_wiring_rec(&self, wiring: [char; 7]) -> [char; 7] {
-        if !wiring.contains(&'x') {
-            return wiring;
-        }
-        let wiring = [
-            Segment::Top,
-            Segment::TopLeft,
-            Segment::TopRight,
-            Segment::Middle,
-            Segment::BottomLeft,
-            Segment::BottomRight,
-            Segment::Bottom,
-        ]
-        .into_iter()
-        .map(|segment| {
-            let by_digit = self.get_possible_wires_by_digit(segment, &wiring);
-            let by_frequency = self.get_possible_wires_by_frequency(segment);
-            if by_digit.len() == 1 {
-                by_digit
-            } else if by_frequency.len() == 1 {
-                by_frequency
+            .map(|d| {
+                table
+                    .decode(d.0, &wiring)
+                    .and_then(|glyph| glyph.to_digit(10))
+                    .ok_or_else(|| anyhow!("Unknown digit {}", d.0))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .try_fold(0u64, |output, value| {
+                output
+                    .checked_mul(10)
+                    .and_then(|output| output.checked_add(value as u64))
+            })
+            .ok_or_else(|| anyhow!("Output has too many digits: {}", self.output.len()))
+    }
+
+    pub(crate) fn deduce_wiring(&self, strategy: Strategy) -> Result<[char; 7]> {
+        self.solve(&GlyphTable::seven_segment(), strategy)?
+            .try_into()
+            .map_err(|v: Vec<char>| anyhow!("Expected 7 wires but was {}", v.len()))
+    }
+
+    // Returns the wire connected to each segment of the table
+    fn solve(&self, table: &GlyphTable, strategy: Strategy) -> Result<Vec<char>> {
+        match self.decode_with(table, strategy)? {
+            Decoding::Unique(wiring) => Ok(wiring),
+            decoding => Err(anyhow!("{}", decoding)),
+        }
+    }
+
+    // The output digits must be glyphs as well, so they take part in the deduction
+    fn patterns(&self) -> Vec<&'a str> {
+        self.pattern
+            .iter()
+            .chain(self.output.iter())
+            .map(|d| d.0)
+            .collect()
+    }
+
+    fn decode_with(&self, table: &GlyphTable, strategy: Strategy) -> Result<Decoding> {
+        match strategy {
+            Strategy::Constraint => Ok(self.decode(table)),
+            Strategy::BruteForce if *table == GlyphTable::seven_segment() => Ok(self.brute_force()),
+            Strategy::BruteForce => Err(anyhow!("Brute force only decodes seven segment digits")),
+        }
+    }
+
+    fn decode(&self, table: &GlyphTable) -> Decoding {
+        let patterns = self.patterns();
+        let mut wirings = match Decoder::new(table, &patterns) {
+            Ok(decoder) => decoder.solve(MAX_WIRINGS),
+            Err(_) => Vec::new(),
+        };
+        match wirings.len() {
+            0 => {}
+            1 => return Decoding::Unique(wirings.remove(0)),
+            _ => return Decoding::Ambiguous(wirings),
+        }
+        // The first pattern that makes the signal unsolvable is the one in conflict
+        let conflict = (1..=patterns.len())
+            .find(|end| match Decoder::new(table, &patterns[..*end]) {
+                Ok(decoder) => decoder.solve(1).is_empty(),
+                Err(_) => true,
+            })
+            .unwrap_or(patterns.len());
+        Decoding::Inconsistent(patterns.get(conflict - 1).unwrap_or(&"").to_string())
+    }
+
+    // Tries every assignment of the seven wires to segments
+    fn brute_force(&self) -> Decoding {
+        let patterns = self.patterns();
+        let masks = patterns
+            .iter()
+            .map(|pattern| {
+                let mask = pattern
+                    .chars()
+                    .try_fold(0u8, |mask, c| wire_index(c, 7).ok().map(|i| mask | 1 << i))?;
+                Some(mask).filter(|mask| mask.count_ones() as usize == pattern.len())
+            })
+            .collect::<Vec<_>>();
+        let mut wirings = Vec::new();
+        let mut conflict = 0;
+        for permutation in (0..7).permutations(7) {
+            let shown = masks
+                .iter()
+                .take_while(|mask| {
+                    mask.map(|mask| {
+                        let lit = (0..7)
+                            .filter(|wire| mask & 1 << wire != 0)
+                            .fold(0u8, |lit, wire| lit | 1 << permutation[wire]);
+                        DIGIT_GLYPHS.contains(&lit)
+                    })
+                    .unwrap_or(false)
+                })
+                .count();
+            if shown == masks.len() {
+                let mut wiring = vec!['x'; 7];
+                for (wire, segment) in permutation.into_iter().enumerate() {
+                    wiring[segment] = (b'a' + wire as u8) as char;
+                }
+                wirings.push(wiring);
             } else {
-                by_digit
-                    .union(&by_frequency)
-                    .filter(|c| !wiring.contains(c))
-                    .cloned()
-                    .collect::<HashSet<char>>()
+                conflict = conflict.max(shown);
             }
-        })
-        .enumerate()
-        .map(|(index, chars)| {
-            if wiring[index] != 'x' {
-                wiring[index]
-            } else if chars.len() == 1 {
-                chars.into_iter().next().unwrap()
-            } else {
-                'x'
+        }
+        match wirings.len() {
+            0 => Decoding::Inconsistent(patterns.get(conflict).unwrap_or(&"").to_string()),
+            1 => Decoding::Unique(wirings.remove(0)),
+            _ => Decoding::Ambiguous(wirings),
+        }
+    }
+
+    // Draws the output as wired, followed by the output through the deduced wiring
+    fn render(&self, strategy: Strategy) -> String {
+        let raw = render_digits(self.output.iter().map(Digit::raw_segments));
+        let outputs = self
+            .output
+            .iter()
+            .map(|d| d.0)
+            .collect::<Vec<_>>()
+            .join(" ");
+        match self.deduce_wiring(strategy) {
+            Ok(wiring) => {
+                let decoded = render_digits(self.output.iter().map(|d| d.segments(&wiring)));
+                let value = self
+                    .get_output(strategy)
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|e| e.to_string());
+                format!(
+                    "{} -> {}\n{}",
+                    outputs,
+                    value,
+                    raw.iter()
+                        .zip(decoded.iter())
+                        .map(|(raw, decoded)| format!("{}   {}", raw, decoded))
+                        .join("\n")
+                )
             }
-        })
-        .collect::<Vec<char>>()
-        .try_into()
-        .unwrap();
-        self.deduce_wiring_rec(wiring)
-    }
-
-    fn get_possible_wires_by_frequency(&self, segment: Segment) -> HashSet<char> {
-        ('a'..='g')
-            .filter(|c| {
-                self.get_wire_frequency(*c)
-                    == match segment {
-                        Segment::Top => 8,
-                        Segment::TopLeft => 6,
-                        Segment::TopRight => 8,
-                        Segment::Middle => 7,
-                        Segment::BottomLeft => 4,
-                        Segment::BottomRight => 9,
-                        Segment::Bottom => 7,
-                    }
+            Err(e) => format!("{} -> {}\n{}", outputs, e, raw.join("\n")),
+        }
+    }
+
+    fn decode_output(&self, table: &GlyphTable, strategy: Strategy) -> Result<String> {
+        let wiring = self.solve(table, strategy)?;
+        self.output
+            .iter()
+            .map(|d| {
+                table
+                    .decode(d.0, &wiring)
+                    .ok_or_else(|| anyhow!("Unknown glyph {}", d.0))
             })
             .collect()
     }
+}
 
-    fn get_possible_wires_by_digit(&self, segment: Segment, wiring: &[char; 7]) -> HashSet<char> {
-        self.pattern
+impl Display for Decoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decoding::Unique(wiring) => write!(f, "wired {}", wiring.iter().collect::<String>()),
+            Decoding::Ambiguous(wirings) => write!(
+                f,
+                "ambiguous between {}{}",
+                wirings
+                    .iter()
+                    .map(|wiring| wiring.iter().collect::<String>())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                if wirings.len() == MAX_WIRINGS {
+                    " and possibly more"
+                } else {
+                    ""
+                }
+            ),
+            Decoding::Inconsistent(pattern) => {
+                write!(f, "inconsistent, no wiring can show {}", pattern)
+            }
+        }
+    }
+}
+
+impl Segment {
+    const ALL: [Segment; 7] = [
+        Segment::Top,
+        Segment::TopLeft,
+        Segment::TopRight,
+        Segment::Middle,
+        Segment::BottomLeft,
+        Segment::BottomRight,
+        Segment::Bottom,
+    ];
+}
+
+impl GlyphTable {
+    // Each non-empty line is a glyph followed by the segments it lights,
+    // with segments named a, b, c, ...
+    fn parse(input: &str) -> Result<GlyphTable> {
+        let glyphs = input
+            .split('\n')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(
+                |line| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    [glyph, segments] if glyph.chars().count() == 1 => Ok((
+                        glyph.chars().next().unwrap(),
+                        segments
+                            .chars()
+                            .try_fold(0u32, |mask, c| wire_index(c, 32).map(|i| mask | 1 << i))?,
+                    )),
+                    _ => Err(anyhow!("Invalid glyph {}", line)),
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+        let segments = 32 - glyphs.iter().fold(0, |all, (_, g)| all | g).leading_zeros();
+        GlyphTable::new(segments as usize, glyphs)
+    }
+
+    fn new(segments: usize, glyphs: Vec<(char, u32)>) -> Result<GlyphTable> {
+        if glyphs.is_empty() {
+            bail!("Glyph table is empty");
+        }
+        for (index, (glyph, segments)) in glyphs.iter().enumerate() {
+            if let Some((other, _)) = glyphs[..index].iter().find(|(_, s)| s == segments) {
+                bail!("Glyphs {} and {} light the same segments", other, glyph);
+            }
+        }
+        Ok(GlyphTable { segments, glyphs })
+    }
+
+    fn seven_segment() -> GlyphTable {
+        GlyphTable {
+            segments: 7,
+            glyphs: (0..=9)
+                .map(|n| {
+                    (
+                        char::from_digit(n as u32, 10).unwrap(),
+                        Segment::for_digit(n)
+                            .into_iter()
+                            .map(|s| 1 << s as u32)
+                            .sum(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn all_segments(&self) -> u32 {
+        ((1u64 << self.segments) - 1) as u32
+    }
+
+    fn unique_lengths(&self) -> Vec<usize> {
+        let lengths = self
+            .glyphs
+            .iter()
+            .map(|(_, g)| g.count_ones() as usize)
+            .collect::<Vec<_>>();
+        lengths
+            .iter()
+            .filter(|l| lengths.iter().filter(|other| other == l).count() == 1)
+            .cloned()
+            .collect()
+    }
+
+    fn decode(&self, pattern: &str, wiring: &[char]) -> Option<char> {
+        let lit = wiring
+            .iter()
+            .enumerate()
+            .filter(|(_, wire)| pattern.contains(**wire))
+            .fold(0u32, |lit, (segment, _)| lit | 1 << segment);
+        if lit.count_ones() as usize != pattern.len() {
+            return None;
+        }
+        self.glyphs
+            .iter()
+            .find(|(_, g)| *g == lit)
+            .map(|(glyph, _)| *glyph)
+    }
+}
+
+fn wire_index(wire: char, wires: usize) -> Result<usize> {
+    let index = (wire as usize).wrapping_sub('a' as usize);
+    if index < wires {
+        Ok(index)
+    } else {
+        Err(anyhow!("Invalid wire {}", wire))
+    }
+}
+
+impl<'t> Decoder<'t> {
+    fn new(table: &'t GlyphTable, patterns: &[&str]) -> Result<Decoder<'t>> {
+        let mut masks = Vec::new();
+        for pattern in patterns {
+            let mask = pattern.chars().try_fold(0u32, |mask, c| {
+                wire_index(c, table.segments).map(|i| mask | 1 << i)
+            })?;
+            if mask.count_ones() as usize != pattern.len() {
+                bail!("Repeated wire in {}", pattern);
+            }
+            // The same glyph shown twice carries no extra information
+            if !masks.contains(&mask) {
+                masks.push(mask);
+            }
+        }
+        Ok(Decoder {
+            table,
+            patterns: masks,
+        })
+    }
+
+    // Finds at most limit wirings, each giving the wire for every segment
+    fn solve(&self, limit: usize) -> Vec<Vec<char>> {
+        let mut solutions = Vec::new();
+        if self.patterns.len() <= self.table.glyphs.len() {
+            self.search(self.initial_constraints(), limit, &mut solutions);
+        }
+        solutions
+    }
+
+    fn initial_constraints(&self) -> Constraints {
+        let all = self.table.all_segments();
+        let mut domains = vec![all; self.table.segments];
+        // When every glyph is shown, a wire must appear in exactly as many
+        // patterns as its segment appears in glyphs
+        if self.patterns.len() == self.table.glyphs.len() {
+            for (wire, domain) in domains.iter_mut().enumerate() {
+                let frequency = self.patterns.iter().filter(|p| *p & 1 << wire != 0).count();
+                *domain = (0..self.table.segments)
+                    .filter(|segment| {
+                        self.table
+                            .glyphs
+                            .iter()
+                            .filter(|(_, g)| g & 1 << segment != 0)
+                            .count()
+                            == frequency
+                    })
+                    .fold(0, |domain, segment| domain | 1 << segment);
+            }
+        }
+        let candidates = self
+            .patterns
             .iter()
-            .filter(|digit| digit.may_activate_segment(segment, wiring))
-            .fold(('a'..='g').collect(), |set, digit| {
-                set.intersection(&digit.0.chars().collect())
-                    .cloned()
+            .map(|p| {
+                (0..self.table.glyphs.len())
+                    .filter(|g| self.table.glyphs[*g].1.count_ones() == p.count_ones())
                     .collect()
             })
+            .collect();
+        Constraints {
+            domains,
+            candidates,
+        }
     }
 
-    fn get_wire_frequency(&self, c: char) -> usize {
-        self.pattern.iter().filter(|d| d.0.contains(c)).count()
+    fn search(&self, mut constraints: Constraints, limit: usize, solutions: &mut Vec<Vec<char>>) {
+        if solutions.len() >= limit || !self.propagate(&mut constraints) {
+            return;
+        }
+        let undecided = constraints
+            .domains
+            .iter()
+            .enumerate()
+            .filter(|(_, domain)| domain.count_ones() > 1)
+            .min_by_key(|(_, domain)| domain.count_ones());
+        match undecided {
+            Some((wire, domain)) => {
+                for segment in 0..self.table.segments {
+                    if domain & 1 << segment != 0 {
+                        let mut guess = constraints.clone();
+                        guess.domains[wire] = 1 << segment;
+                        self.search(guess, limit, solutions);
+                    }
+                }
+            }
+            None => {
+                if let Some(wiring) = self.wiring(&constraints) {
+                    solutions.push(wiring);
+                }
+            }
+        }
+    }
+
+    // Narrows the constraints until nothing changes, returning false on a contradiction
+    fn propagate(&self, constraints: &mut Constraints) -> bool {
+        let all = self.table.all_segments();
+        loop {
+            let before = constraints.clone();
+            for (pattern, candidates) in self.patterns.iter().zip(constraints.candidates.iter_mut())
+            {
+                let domains = &constraints.domains;
+                candidates.retain(|g| {
+                    let glyph = self.table.glyphs[*g].1;
+                    domains.iter().enumerate().all(|(wire, domain)| {
+                        if pattern & 1 << wire != 0 {
+                            domain & glyph != 0
+                        } else {
+                            domain & !glyph & all != 0
+                        }
+                    })
+                });
+                if candidates.is_empty() {
+                    return false;
+                }
+            }
+            // Two patterns can't show the same glyph
+            for index in 0..self.patterns.len() {
+                if let [glyph] = constraints.candidates[index][..] {
+                    for (other, candidates) in constraints.candidates.iter_mut().enumerate() {
+                        if other != index {
+                            candidates.retain(|g| *g != glyph);
+                            if candidates.is_empty() {
+                                return false;
+                            }
+                        }
+                    }
+                }
+            }
+            for (pattern, candidates) in self.patterns.iter().zip(constraints.candidates.iter()) {
+                let lit = candidates
+                    .iter()
+                    .fold(0, |lit, g| lit | self.table.glyphs[*g].1);
+                let unlit = candidates
+                    .iter()
+                    .fold(0, |unlit, g| unlit | !self.table.glyphs[*g].1 & all);
+                for (wire, domain) in constraints.domains.iter_mut().enumerate() {
+                    *domain &= if pattern & 1 << wire != 0 { lit } else { unlit };
+                }
+            }
+            // Two wires can't drive the same segment
+            for wire in 0..self.table.segments {
+                let domain = constraints.domains[wire];
+                if domain.count_ones() == 1 {
+                    for (other, other_domain) in constraints.domains.iter_mut().enumerate() {
+                        if other != wire {
+                            *other_domain &= !domain;
+                        }
+                    }
+                }
+            }
+            if constraints.domains.contains(&0) {
+                return false;
+            }
+            if constraints.domains == before.domains && constraints.candidates == before.candidates
+            {
+                return true;
+            }
+        }
+    }
+
+    fn wiring(&self, constraints: &Constraints) -> Option<Vec<char>> {
+        let mut wiring = vec!['x'; self.table.segments];
+        for (wire, domain) in constraints.domains.iter().enumerate() {
+            wiring[domain.trailing_zeros() as usize] = (b'a' + wire as u8) as char;
+        }
+        let mut shown = Vec::new();
+        for pattern in &self.patterns {
+            let lit = (0..self.table.segments)
+                .filter(|segment| pattern & 1 << (wiring[*segment] as u8 - b'a') != 0)
+                .fold(0, |lit, segment| lit | 1 << segment);
+            match self.table.glyphs.iter().position(|(_, g)| *g == lit) {
+                Some(glyph) if !shown.contains(&glyph) => shown.push(glyph),
+                _ => return None,
+            }
+        }
+        Some(wiring)
     }
 }
 
 impl<'a> Digit<'a> {
-    fn may_activate_segment(&self, segment: Segment, wiring: &[char; 7]) -> bool {
-        self.get_possible_numbers(wiring)
+    // Lights the segments as if wire a drove the top segment, b the top left and so on
+    fn raw_segments(&self) -> Vec<Segment> {
+        Segment::ALL
             .into_iter()
-            .flat_map(|n| self.get_segments(n))
-            .any(|s| s == segment)
-    }
-
-    fn get_possible_numbers(&self, wiring: &[char; 7]) -> HashSet<u8> {
-        match self.0.len() {
-            2 => vec![1],
-            3 => vec![7],
-            4 => vec![4],
-            5 => [2, 3, 5]
-                .into_iter()
-                .filter(|n| match n {
-                    2 => {
-                        !(self.0.contains(wiring[Segment::TopLeft as usize])
-                            || self.0.contains(wiring[Segment::BottomRight as usize]))
-                    }
-                    3 => {
-                        !(self.0.contains(wiring[Segment::TopLeft as usize])
-                            || self.0.contains(wiring[Segment::BottomLeft as usize]))
-                    }
-                    5 => {
-                        !(self.0.contains(wiring[Segment::TopRight as usize])
-                            || self.0.contains(wiring[Segment::BottomLeft as usize]))
-                    }
-                    _ => panic!(),
-                })
-                .collect(),
-            6 => [0, 6, 9]
-                .into_iter()
-                .filter(|n| match n {
-                    0 => !self.0.contains(wiring[Segment::Middle as usize]),
-                    6 => !self.0.contains(wiring[Segment::TopRight as usize]),
-                    9 => !self.0.contains(wiring[Segment::BottomLeft as usize]),
-                    _ => panic!(),
-                })
-                .collect(),
-            7 => vec![8],
-            _ => panic!("Impossible digit {}", self.0),
+            .filter(|segment| self.0.contains((b'a' + *segment as u8) as char))
+            .collect()
+    }
+
+    fn segments(&self, wiring: &[char; 7]) -> Vec<Segment> {
+        Segment::ALL
+            .into_iter()
+            .filter(|segment| self.0.contains(wiring[*segment as usize]))
+            .collect()
+    }
+}
+
+// Each digit is three characters wide and three lines high
+fn render_digits<I: Iterator<Item = Vec<Segment>>>(digits: I) -> Vec<String> {
+    let mut lines = vec![String::new(); 3];
+    for (index, segments) in digits.enumerate() {
+        let mut cells = [[' '; 3]; 3];
+        for segment in segments {
+            let (row, column, c) = segment.position();
+            cells[row][column] = c;
+        }
+        for (line, row) in lines.iter_mut().zip(cells.iter()) {
+            if index > 0 {
+                line.push(' ');
+            }
+            line.extend(row.iter());
         }
-        .into_iter()
-        .collect()
     }
+    lines
+}
 
-    fn get_segments(&self, number: u8) -> Vec<Segment> {
+impl Segment {
+    fn position(self) -> (usize, usize, char) {
+        match self {
+            Segment::Top => (0, 1, '_'),
+            Segment::TopLeft => (1, 0, '|'),
+            Segment::TopRight => (1, 2, '|'),
+            Segment::Middle => (1, 1, '_'),
+            Segment::BottomLeft => (2, 0, '|'),
+            Segment::BottomRight => (2, 2, '|'),
+            Segment::Bottom => (2, 1, '_'),
+        }
+    }
+
+    fn for_digit(number: u8) -> Vec<Segment> {
         match number {
             0 => vec![
                 Segment::Top,
@@ -274,15 +749,7 @@ impl<'a> Digit<'a> {
                 Segment::Bottom,
             ],
             7 => vec![Segment::Top, Segment::TopRight, Segment::BottomRight],
-            8 => vec![
-                Segment::Top,
-                Segment::TopLeft,
-                Segment::TopRight,
-                Segment::Middle,
-                Segment::BottomLeft,
-                Segment::BottomRight,
-                Segment::Bottom,
-            ],
+            8 => Segment::ALL.to_vec(),
             9 => vec![
                 Segment::Top,
                 Segment::TopLeft,
@@ -294,25 +761,62 @@ impl<'a> Digit<'a> {
             _ => panic!("Invalid number {}", number),
         }
     }
-
-    fn get_value(&self, wiring: &[char; 7]) -> u8 {
-        (0..=9)
-            .find(|n| {
-                let segments = self.get_segments(*n);
-                segments.len() == self.0.len()
-                    && segments
-                        .into_iter()
-                        .all(|segment| self.0.contains(wiring[segment as usize]))
-            })
-            .unwrap()
-    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{Digit, Signal};
+    use crate::{
+        parse_signals, render_digits, Decoder, Decoding, Digit, GlyphTable, Segment, Signal,
+        Strategy, DIGIT_GLYPHS, MAX_WIRINGS,
+    };
     use yare::parameterized;
 
+    // The cross-check permutes and corrupts signals with a seeded linear congruential generator
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) as usize % bound
+        }
+    }
+
+    // Sixteen segments named a to p
+    const HEX: &str = "0 abcdefghmn
+1 bcdfm
+2 abcefgijp
+3 abcdefj
+4 cdhij
+5 abdefhij
+6 abdefghij
+7 abcd
+8 abcdefghij
+9 abcdefhij
+A abcdghij
+B abcdefjlo
+C abefgh
+D abcdefl
+E abefghi
+F abghik";
+
+    // Applies the wire permutation to the glyphs of the table
+    fn scramble(table: &GlyphTable, permutation: &[char], glyphs: &str) -> String {
+        glyphs
+            .chars()
+            .map(|glyph| {
+                let (_, segments) = table.glyphs.iter().find(|(g, _)| *g == glyph).unwrap();
+                (0..table.segments)
+                    .filter(|s| segments & 1 << s != 0)
+                    .map(|s| permutation[s])
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     #[parameterized{
         one = { ["ab", "abcdef", "abcdef", "abcde"], 1},
         two = { ["ab", "abc", "abcdef", "abcdef"], 2},
@@ -320,15 +824,14 @@ mod test {
         four = { ["ab", "abcd", "abc", "abcdefg"], 4},
     }]
     fn test_known_output_digits(output_digits: [&str; 4], expected: u32) {
-        let pattern = (0..10)
-            .map(|_| Digit("a"))
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-        let output = output_digits.map(|d| Digit(d));
+        let pattern = (0..10).map(|_| Digit("a")).collect();
+        let output = output_digits.map(Digit).to_vec();
         let signal = Signal { pattern, output };
 
-        assert_eq!(expected, signal.known_output_digits())
+        assert_eq!(
+            expected,
+            signal.known_output_digits(&GlyphTable::seven_segment())
+        )
     }
 
     #[parameterized{
@@ -343,8 +846,12 @@ mod test {
         eight = { "abcdefg", 8 },
         nine = { "abcdfg", 9 },
     }]
-    fn test_get_value() {
-        let _wiring: [char; 7] = ('a'..='g').collect::<Vec<_>>().try_into().unwrap();
+    fn test_get_value(pattern: &str, expected: u8) {
+        let wiring = ('a'..='g').collect::<Vec<_>>();
+        assert_eq!(
+            char::from_digit(expected as u32, 10),
+            GlyphTable::seven_segment().decode(pattern, &wiring)
+        );
     }
 
     #[test]
@@ -353,7 +860,7 @@ mod test {
             "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
         )
         .unwrap();
-        let wiring = signal.deduce_wiring();
+        let wiring = signal.deduce_wiring(Strategy::Constraint).unwrap();
         assert_eq!(['d', 'e', 'a', 'f', 'g', 'b', 'c'], wiring);
     }
 
@@ -363,6 +870,282 @@ mod test {
             "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
         )
         .unwrap();
-        assert_eq!(5353, signal.get_output());
+        assert_eq!(5353, signal.get_output(Strategy::Constraint).unwrap());
+    }
+
+    #[test]
+    fn test_parse_glyph_table() {
+        let table = GlyphTable::parse("0 abcefg\n1 cf\n\n7 acf\n").unwrap();
+        assert_eq!(7, table.segments);
+        assert_eq!(
+            vec![('0', 0b1110111), ('1', 0b0100100), ('7', 0b0100101)],
+            table.glyphs
+        );
+        assert!(GlyphTable::parse("0 abc\n1 cba").is_err());
+        assert!(GlyphTable::parse("0 ab!").is_err());
+    }
+
+    #[parameterized{
+        identity = { "abcdefghijklmnop", "DEADBEEF" },
+        reversed = { "ponmlkjihgfedcba", "C0FFEE42" },
+        shuffled = { "gpdkambhoicnelfj", "7A1B9F30" },
+    }]
+    fn test_decode_sixteen_segment(permutation: &str, output: &str) {
+        let table = GlyphTable::parse(HEX).unwrap();
+        let permutation = permutation.chars().collect::<Vec<_>>();
+        let line = format!(
+            "{} | {}",
+            scramble(&table, &permutation, "0123456789ABCDEF"),
+            scramble(&table, &permutation, output)
+        );
+        let signal = Signal::parse(&line).unwrap();
+        let patterns = signal.pattern.iter().map(|d| d.0).collect::<Vec<_>>();
+        let decoder = Decoder::new(&table, &patterns).unwrap();
+        assert_eq!(vec![permutation.clone()], decoder.solve(2));
+        assert_eq!(
+            permutation,
+            signal.solve(&table, Strategy::Constraint).unwrap()
+        );
+        assert_eq!(
+            output,
+            signal.decode_output(&table, Strategy::Constraint).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_partial_patterns() {
+        let table = GlyphTable::seven_segment();
+        let signal = Signal::parse("cf acf bcdf abcdefg abdfg acdeg | abdfg cf").unwrap();
+        assert_eq!(
+            "51",
+            signal.decode_output(&table, Strategy::Constraint).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_unique() {
+        let signal = Signal::parse(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        )
+        .unwrap();
+        assert_eq!(
+            Decoding::Unique("deafgbc".chars().collect()),
+            signal.decode(&GlyphTable::seven_segment())
+        );
+    }
+
+    #[parameterized{
+        partial = { "cf acf bcdf abcdefg abdfg | abdfg cf", vec!["abcdefg", "adcbefg"] },
+        // Only d is pinned to the top, the wirings listed before the cap all put a on c and b on f
+        outputs_only = {
+            "ab dab | ab dab",
+            vec![
+                "dcaefbg", "dcaegbf", "dcafebg", "dcagebf", "dcafgbe", "dcagfbe", "deacfbg",
+                "deacgbf", "dfacebg", "dgacebf", "dfacgbe", "dgacfbe", "deafcbg", "deagcbf",
+                "dfaecbg", "dgaecbf", "dfagcbe", "dgafcbe", "deafgbc", "deagfbc", "dfaegbc",
+                "dgaefbc", "dfagebc", "dgafebc",
+            ]
+        },
+    }]
+    fn test_decode_ambiguous(line: &str, expected: Vec<&str>) {
+        let signal = Signal::parse(line).unwrap();
+        match signal.decode(&GlyphTable::seven_segment()) {
+            Decoding::Ambiguous(wirings) => {
+                let wirings = wirings
+                    .into_iter()
+                    .map(|wiring| wiring.into_iter().collect::<String>())
+                    .collect::<Vec<_>>();
+                assert_eq!(expected, wirings);
+            }
+            decoding => panic!("Expected ambiguous decoding but got {:?}", decoding),
+        }
+    }
+
+    #[parameterized{
+        invalid_wire = { "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadh", "fcadh" },
+        repeated_wire = { "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcdd", "fcdd" },
+        impossible_length = { "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb a | cdfeb", "a" },
+        two_fours = { "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb gabf", "gabf" },
+        eleven_digits = { "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fadeb", "fadeb" },
+    }]
+    fn test_decode_inconsistent(line: &str, pattern: &str) {
+        let signal = Signal::parse(line).unwrap();
+        assert_eq!(
+            Decoding::Inconsistent(pattern.to_string()),
+            signal.decode(&GlyphTable::seven_segment())
+        );
+        assert_eq!(
+            Decoding::Inconsistent(pattern.to_string()),
+            signal.brute_force()
+        );
+        assert!(signal.get_output(Strategy::Constraint).is_err());
+    }
+
+    #[test]
+    fn test_unsatisfiable_signal() {
+        let signal = Signal::parse("ab abc abcd abcde abcdef | ab").unwrap();
+        assert!(signal.deduce_wiring(Strategy::Constraint).is_err());
+    }
+
+    #[test]
+    fn test_digit_glyphs() {
+        let table = GlyphTable::seven_segment();
+        let glyphs = table
+            .glyphs
+            .iter()
+            .map(|(_, g)| *g as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(DIGIT_GLYPHS.to_vec(), glyphs);
+    }
+
+    #[test]
+    fn test_brute_force_sample() {
+        let signal = Signal::parse(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        )
+        .unwrap();
+        assert_eq!(
+            ['d', 'e', 'a', 'f', 'g', 'b', 'c'],
+            signal.deduce_wiring(Strategy::BruteForce).unwrap()
+        );
+        assert_eq!(5353, signal.get_output(Strategy::BruteForce).unwrap());
+        let table = GlyphTable::parse("0 ab\n1 a").unwrap();
+        assert!(signal.decode_with(&table, Strategy::BruteForce).is_err());
+    }
+
+    #[test]
+    fn test_brute_force_cross_check() {
+        let table = GlyphTable::seven_segment();
+        let mut random = Lcg(8);
+        for round in 0..300 {
+            let mut permutation = ('a'..='g').collect::<Vec<_>>();
+            for i in (1..7).rev() {
+                permutation.swap(i, random.below(i + 1));
+            }
+            let mut digits = "0123456789".chars().collect::<Vec<_>>();
+            for i in (1..10).rev() {
+                digits.swap(i, random.below(i + 1));
+            }
+            // Every third signal hides some of its digits
+            let shown = if round % 3 == 0 {
+                3 + random.below(7)
+            } else {
+                10
+            };
+            let output = (0..4)
+                .map(|_| char::from_digit(random.below(10) as u32, 10).unwrap())
+                .collect::<String>();
+            let mut patterns = scramble(
+                &table,
+                &permutation,
+                &digits[..shown].iter().collect::<String>(),
+            );
+            // Corrupting toggles one wire in one pattern
+            if round % 5 == 0 {
+                let mut corrupted = patterns.split(' ').map(String::from).collect::<Vec<_>>();
+                let index = random.below(corrupted.len());
+                let wire = (b'a' + random.below(7) as u8) as char;
+                match corrupted[index].find(wire) {
+                    Some(position) => {
+                        corrupted[index].remove(position);
+                    }
+                    None => corrupted[index].push(wire),
+                }
+                patterns = corrupted.join(" ");
+            }
+            let line = format!("{} | {}", patterns, scramble(&table, &permutation, &output));
+            let signal = Signal::parse(&line).unwrap();
+
+            let expected = signal.brute_force();
+            match (signal.decode(&table), &expected) {
+                (Decoding::Ambiguous(mut wirings), Decoding::Ambiguous(all))
+                    if all.len() >= MAX_WIRINGS =>
+                {
+                    assert_eq!(MAX_WIRINGS, wirings.len(), "{}", line);
+                    wirings.retain(|wiring| !all.contains(wiring));
+                    assert!(wirings.is_empty(), "{}", line);
+                }
+                (Decoding::Ambiguous(mut wirings), Decoding::Ambiguous(all)) => {
+                    let mut all = all.clone();
+                    wirings.sort();
+                    all.sort();
+                    assert_eq!(all, wirings, "{}", line);
+                }
+                (decoding, expected) => assert_eq!(*expected, decoding, "{}", line),
+            }
+            if shown == 10 && round % 5 != 0 {
+                assert_eq!(Decoding::Unique(permutation.clone()), expected, "{}", line);
+                assert_eq!(
+                    signal.deduce_wiring(Strategy::BruteForce).unwrap(),
+                    signal.deduce_wiring(Strategy::Constraint).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_digits() {
+        let lines = render_digits((0..=9).map(Segment::for_digit));
+        assert_eq!(
+            vec![
+                " _       _   _       _   _   _   _   _ ",
+                "| |   |  _|  _| |_| |_  |_    | |_| |_|",
+                "|_|   | |_   _|   |  _| |_|   | |_|  _|",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_render_signal() {
+        let signal = Signal::parse(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        )
+        .unwrap();
+        let raw = render_digits(signal.output.iter().map(Digit::raw_segments));
+        let decoded = render_digits([5, 3, 5, 3].into_iter().map(Segment::for_digit));
+        let expected = format!(
+            "cdfeb fcadb cdfeb cdbaf -> 5353\n{}   {}\n{}   {}\n{}   {}",
+            raw[0], decoded[0], raw[1], decoded[1], raw[2], decoded[2]
+        );
+        assert_eq!(expected, signal.render(Strategy::Constraint));
+        assert_eq!("     _       _ ", raw[0]);
+    }
+
+    #[test]
+    fn test_long_output() {
+        let patterns = "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab";
+        let line = format!("{} | {}", patterns, vec!["acedgfb"; 19].join(" "));
+        let signal = Signal::parse(&line).unwrap();
+        assert_eq!(
+            8888888888888888888,
+            signal.get_output(Strategy::Constraint).unwrap()
+        );
+        let line = format!("{} | {}", patterns, vec!["acedgfb"; 20].join(" "));
+        let signal = Signal::parse(&line).unwrap();
+        assert!(signal.get_output(Strategy::Constraint).is_err());
+    }
+
+    #[test]
+    fn test_parse_signals() {
+        let line =
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
+        let input = format!("{}\nGARBAGE\n\n{}\n", line, line);
+        let signals = parse_signals(&input);
+        assert_eq!(
+            vec![1, 2, 4],
+            signals.iter().map(|(line, _)| *line).collect::<Vec<_>>()
+        );
+        assert!(signals[0].1.is_ok());
+        assert!(signals[1].1.is_err());
+        assert_eq!(
+            5353,
+            signals[2]
+                .1
+                .as_ref()
+                .unwrap()
+                .get_output(Strategy::Constraint)
+                .unwrap()
+        );
     }
 }
@@ -1,10 +1,9 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use itertools::Itertools;
-use std::cell::RefCell;
-use std::collections::{HashSet, VecDeque};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader};
 
 #[derive(Parser)]
 #[clap(version = "1.0", author = "Raniz")]
@@ -15,13 +14,17 @@ struct Opts {
     basins: bool,
 }
 
-struct Map<const N: usize, const M: usize>([[u8; M]; N]);
+// Heights are stored row by row
+#[derive(Debug, Default)]
+struct Map {
+    width: usize,
+    height: usize,
+    heights: Vec<u8>,
+}
 
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
-    let mut input = String::new();
-    File::open(opts.input)?.read_to_string(&mut input)?;
-    let map: Map<100, 100> = Map::parse(input.split('\n'))?;
+    let map = Map::read(BufReader::new(File::open(opts.input)?))?;
     let score = if opts.basins {
         map.find_basins()
             .into_iter()
@@ -38,41 +41,66 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-impl<const N: usize, const M: usize> Map<N, M> {
-    fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Map<N, M>> {
-        Ok(Map(lines
-            .map(|line| -> Result<[u8; M]> {
-                line.chars()
-                    .map(|c| match c {
-                        '0'..='9' => Ok(c.to_digit(10).unwrap() as u8),
-                        _ => Err(anyhow!("Invalid integer {}", c)),
-                    })
-                    .collect::<Result<Vec<u8>>>()?
-                    .try_into()
-                    .map_err(|v: Vec<u8>| {
-                        anyhow!("Expected {} columns but was only {}", M, v.len())
-                    })
-            })
-            .collect::<Result<Vec<_>>>()?
-            .try_into()
-            .map_err(|v: Vec<[u8; M]>| {
-                anyhow!("Expected {} rows but was only {}", N, v.len())
-            })?))
+impl Map {
+    fn read(reader: impl BufRead) -> Result<Map> {
+        let mut map = Map::default();
+        // Blank lines, like the one at the end of the input, aren't rows
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                map.push_row(&line)?;
+            }
+        }
+        Ok(map)
+    }
+
+    // The first row decides the width of the map
+    fn push_row(&mut self, line: &str) -> Result<()> {
+        let start = self.heights.len();
+        for c in line.chars() {
+            match c {
+                '0'..='9' => self.heights.push(c as u8 - b'0'),
+                _ => return Err(anyhow!("Invalid integer {}", c)),
+            }
+        }
+        let width = self.heights.len() - start;
+        if self.height == 0 {
+            self.width = width;
+        } else if width != self.width {
+            return Err(anyhow!(
+                "Expected {} columns but row {} has {}",
+                self.width,
+                self.height + 1,
+                width
+            ));
+        }
+        self.height += 1;
+        Ok(())
+    }
+
+    fn get(&self, row: usize, col: usize) -> u8 {
+        self.heights[row * self.width + col]
+    }
+
+    fn neighbors(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (height, width) = (self.height, self.width);
+        [
+            row.checked_sub(1).map(|r| (r, col)),
+            Some(row + 1).filter(|r| *r < height).map(|r| (r, col)),
+            col.checked_sub(1).map(|c| (row, c)),
+            Some(col + 1).filter(|c| *c < width).map(|c| (row, c)),
+        ]
+        .into_iter()
+        .flatten()
     }
 
     fn find_lowpoints(&self) -> Vec<u8> {
-        (0..N)
+        (0..self.height)
             .flat_map(|row| {
-                (0..M).filter_map(move |col| {
-                    Some(self.0[row][col]).filter(|value| {
-                        [(-1i32, 0i32), (1i32, 0i32), (0i32, -1i32), (0i32, 1i32)]
-                            .iter()
-                            .map(|(dx, dy)| (row as i32 + dx, col as i32 + dy))
-                            .filter(|(x, y)| {
-                                x >= &0 && y >= &0 && x < &(N as i32) && y < &(M as i32)
-                            })
-                            .map(|(x, y)| self.0[x as usize][y as usize])
-                            .all(|neighbor| value < &neighbor)
+                (0..self.width).filter_map(move |col| {
+                    Some(self.get(row, col)).filter(|value| {
+                        self.neighbors(row, col)
+                            .all(|(r, c)| *value < self.get(r, c))
                     })
                 })
             })
@@ -80,75 +108,57 @@ impl<const N: usize, const M: usize> Map<N, M> {
     }
 
     fn find_basins(&self) -> Vec<Vec<(usize, usize)>> {
-        let visited: RefCell<HashSet<(usize, usize)>> = RefCell::new(HashSet::new());
-        (0..N)
-            .flat_map(|row| {
-                let visited = &visited;
-                (0..M).filter_map(move |col| {
-                    let mut coords = Vec::new();
-                    let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
-                    queue.push_back((row, col));
-                    while let Some((walk_row, walk_col)) = queue.pop_front() {
-                        if visited.borrow().contains(&(walk_row, walk_col))
-                            || self.0[walk_row][walk_col] == 9
-                        {
-                            continue;
-                        }
-                        coords.push((walk_row, walk_col));
-                        visited.borrow_mut().insert((walk_row, walk_col));
-                        for (dr, dc) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
-                            let next_row = match dr {
-                                -1 => walk_row.checked_sub(1),
-                                _ => walk_row.checked_add(dr as usize).filter(|r| r < &N),
-                            };
-                            let next_col = match dc {
-                                -1 => walk_col.checked_sub(1),
-                                _ => walk_col.checked_add(dc as usize).filter(|c| c < &M),
-                            };
-                            if let (Some(next_row), Some(next_col)) = (next_row, next_col) {
-                                queue.push_back((next_row, next_col))
-                            }
+        let mut visited = vec![false; self.heights.len()];
+        let mut basins = Vec::new();
+        let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                if visited[row * self.width + col] || self.get(row, col) == 9 {
+                    continue;
+                }
+                let mut coords = Vec::new();
+                visited[row * self.width + col] = true;
+                queue.push_back((row, col));
+                while let Some((walk_row, walk_col)) = queue.pop_front() {
+                    coords.push((walk_row, walk_col));
+                    for (next_row, next_col) in self.neighbors(walk_row, walk_col) {
+                        let index = next_row * self.width + next_col;
+                        if !visited[index] && self.heights[index] != 9 {
+                            visited[index] = true;
+                            queue.push_back((next_row, next_col));
                         }
                     }
-                    if !coords.is_empty() {
-                        Some(coords)
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect()
+                }
+                basins.push(coords);
+            }
+        }
+        basins
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::Map;
+    use itertools::Itertools;
     use maplit::hashset;
     use std::collections::HashSet;
 
+    const SAMPLE: &str = "2199943210
+3987894921
+9856789892
+8767896789
+9899965678";
+
     #[test]
     fn test_find_lowpoints() {
-        let map = Map([
-            [2u8, 1u8, 9u8, 9u8, 9u8, 4u8, 3u8, 2u8, 1u8, 0u8],
-            [3u8, 9u8, 8u8, 7u8, 8u8, 9u8, 4u8, 9u8, 2u8, 1u8],
-            [9u8, 8u8, 5u8, 6u8, 7u8, 8u8, 9u8, 8u8, 9u8, 2u8],
-            [8u8, 7u8, 6u8, 7u8, 8u8, 9u8, 6u8, 7u8, 8u8, 9u8],
-            [9u8, 8u8, 9u8, 9u8, 9u8, 6u8, 5u8, 6u8, 7u8, 8u8],
-        ]);
+        let map = Map::read(SAMPLE.as_bytes()).unwrap();
         let lowpoints = map.find_lowpoints();
         assert_eq!(vec![1, 0, 5, 5], lowpoints);
     }
 
     #[test]
     fn test_find_basins() {
-        let map = Map([
-            [2u8, 1u8, 9u8, 9u8, 9u8, 4u8, 3u8, 2u8, 1u8, 0u8],
-            [3u8, 9u8, 8u8, 7u8, 8u8, 9u8, 4u8, 9u8, 2u8, 1u8],
-            [9u8, 8u8, 5u8, 6u8, 7u8, 8u8, 9u8, 8u8, 9u8, 2u8],
-            [8u8, 7u8, 6u8, 7u8, 8u8, 9u8, 6u8, 7u8, 8u8, 9u8],
-            [9u8, 8u8, 9u8, 9u8, 9u8, 6u8, 5u8, 6u8, 7u8, 8u8],
-        ]);
+        let map = Map::read(SAMPLE.as_bytes()).unwrap();
         let expected = vec![
             hashset![(0, 0), (0, 1), (1, 0)],
             hashset![
@@ -197,4 +207,47 @@ mod test {
             .collect();
         assert_eq!(expected, basins);
     }
+
+    #[test]
+    fn test_read() {
+        let map = Map::read("123\n456\n".as_bytes()).unwrap();
+        assert_eq!((3, 2), (map.width, map.height));
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], map.heights);
+        assert!(Map::read("123\n45\n".as_bytes()).is_err());
+        assert!(Map::read("12x\n".as_bytes()).is_err());
+
+        let map = Map::read("123\n\n456\n\n".as_bytes()).unwrap();
+        assert_eq!((3, 2), (map.width, map.height));
+    }
+
+    #[test]
+    fn test_find_lowpoints_narrow() {
+        let map = Map::read("5\n3\n4\n9\n1".as_bytes()).unwrap();
+        assert_eq!(vec![3, 1], map.find_lowpoints());
+        let map = Map::read("53491".as_bytes()).unwrap();
+        assert_eq!(vec![3, 1], map.find_lowpoints());
+    }
+
+    #[test]
+    fn test_large_map() {
+        // Walls of nines every tenth row and column split the map into 9x9 basins
+        let (width, height) = (2000, 1500);
+        let input = (0..height)
+            .map(|row| {
+                (0..width)
+                    .map(|col| match (row % 10, col % 10) {
+                        (9, _) | (_, 9) => '9',
+                        (4, 4) => '0',
+                        _ => '5',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n");
+        let map = Map::read(input.as_bytes()).unwrap();
+        assert_eq!((width, height), (map.width, map.height));
+        assert_eq!(width / 10 * height / 10, map.find_lowpoints().len());
+        let basins = map.find_basins();
+        assert_eq!(width / 10 * height / 10, basins.len());
+        assert!(basins.iter().all(|basin| basin.len() == 81));
+    }
 }